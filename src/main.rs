@@ -10,7 +10,7 @@ fn main() {
     let address = azalea_protocol::ServerAddress::try_from("localhost:13157").unwrap();
 
     ClientBuilder::new(&account, &address)
-        .add_plugins(RenderPlugin {})
+        .add_plugins(RenderPlugin::default())
         .add_plugins(PlayerPlugin)
         .run()
         .unwrap();
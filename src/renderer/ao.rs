@@ -0,0 +1,237 @@
+use azalea_core::direction::Direction;
+
+use super::assets::baked::{direction_normal, BakedQuad};
+
+/// How bright a vertex is at every ambient occlusion level, from fully occluded to not occluded.
+pub const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// Returns the block a quad faces and, for every vertex, the two blocks beside the vertex and the
+/// one diagonal to it, which decide its ambient occlusion and smooth light. Offsets are relative to
+/// the quad's block.
+fn vertex_neighbours(quad: &BakedQuad) -> (glam::IVec3, [[glam::IVec3; 3]; 4]) {
+    let normal = direction_normal(quad.direction);
+    let (axis, u, v) = match quad.direction {
+        Direction::Down | Direction::Up => (1, 0, 2),
+        Direction::North | Direction::South => (2, 0, 1),
+        Direction::West | Direction::East => (0, 1, 2),
+    };
+
+    // Like vanilla, faces on the side of the block are occluded by the blocks next to that side,
+    // faces inside the block by the blocks around the block itself.
+    let plane = if normal[axis] > 0.0 { 1.0 } else { 0.0 };
+    let on_boundary = quad
+        .positions
+        .iter()
+        .all(|position| (position[axis] - plane).abs() < 1e-4);
+    let layer = if on_boundary {
+        normal.as_ivec3()
+    } else {
+        glam::IVec3::ZERO
+    };
+
+    let vertices = quad.positions.map(|position| {
+        let mut side1 = glam::IVec3::ZERO;
+        side1[u] = if position[u] > 0.5 { 1 } else { -1 };
+        let mut side2 = glam::IVec3::ZERO;
+        side2[v] = if position[v] > 0.5 { 1 } else { -1 };
+
+        [layer + side1, layer + side2, layer + side1 + side2]
+    });
+
+    (layer, vertices)
+}
+
+/// Computes the ambient occlusion level of every vertex of `quad`, from 0 for the darkest to 3 for
+/// not occluded. `occludes` is called with offsets relative to the quad's block and returns
+/// whether the block there casts ambient occlusion.
+pub fn quad_ao(quad: &BakedQuad, occludes: impl Fn(glam::IVec3) -> bool) -> [u8; 4] {
+    let (_, vertices) = vertex_neighbours(quad);
+    vertices.map(|[side1, side2, corner]| ao(occludes(side1), occludes(side2), occludes(corner)))
+}
+
+fn ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - ((side1 as u8) + (side2 as u8) + (corner as u8))
+    }
+}
+
+/// Smoothly lights every vertex of `quad` like vanilla's smooth lighting, returns the sky and block
+/// light of each vertex from 0 to 15. `light` returns the sky and block light at an offset relative
+/// to the quad's block, `occludes` whether the block there is opaque.
+pub fn quad_light(
+    quad: &BakedQuad,
+    light: impl Fn(glam::IVec3) -> (u8, u8),
+    occludes: impl Fn(glam::IVec3) -> bool,
+) -> [(f32, f32); 4] {
+    let (layer, vertices) = vertex_neighbours(quad);
+    let center = light(layer);
+
+    vertices.map(|[side1, side2, corner]| {
+        let side1_light = light(side1);
+        // Light can't reach the vertex from a corner that is blocked off on both sides.
+        let corner_light = if occludes(side1) && occludes(side2) {
+            side1_light
+        } else {
+            light(corner)
+        };
+
+        // Blocks without any light, usually solid ones, take the light of the faced block instead
+        // of darkening the vertex.
+        let samples = [center, side1_light, light(side2), corner_light].map(|sample| {
+            if sample == (0, 0) {
+                center
+            } else {
+                sample
+            }
+        });
+
+        let sky: u32 = samples.iter().map(|(sky, _)| *sky as u32).sum();
+        let block: u32 = samples.iter().map(|(_, block)| *block as u32).sum();
+        (sky as f32 / 4.0, block as f32 / 4.0)
+    })
+}
+
+/// The light of a face without smooth lighting, the light of the block it faces.
+pub fn flat_light(quad: &BakedQuad, light: impl Fn(glam::IVec3) -> (u8, u8)) -> (f32, f32) {
+    let (layer, _) = vertex_neighbours(quad);
+    let (sky, block) = light(layer);
+    (sky as f32, block as f32)
+}
+
+/// The indices of the two triangles of a quad. The quad is split along the diagonal with the
+/// brighter corners, so the occlusion is interpolated the same way for every orientation.
+pub fn quad_indices(ao: [u8; 4]) -> [u32; 6] {
+    if ao[1] + ao[3] > ao[0] + ao[2] {
+        [0, 1, 3, 1, 2, 3]
+    } else {
+        [0, 1, 2, 0, 2, 3]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use azalea_core::direction::Direction;
+
+    use super::{quad_ao, quad_indices, quad_light};
+    use crate::renderer::assets::baked::{BakedQuad, FaceMask};
+
+    fn quad(direction: Direction, positions: [[f32; 3]; 4]) -> BakedQuad {
+        BakedQuad {
+            positions: positions.map(Into::into),
+            uvs: [glam::Vec2::ZERO; 4],
+            texture: 0,
+            direction,
+            cullface: None,
+            cull_mask: FaceMask::EMPTY,
+            tint_index: None,
+            shade: true,
+        }
+    }
+
+    fn ao(quad: &BakedQuad, blocks: &[[i32; 3]]) -> [u8; 4] {
+        quad_ao(quad, |offset| blocks.contains(&offset.to_array()))
+    }
+
+    #[test]
+    fn ao_test() {
+        let top = quad(
+            Direction::Up,
+            [
+                [0.0, 1.0, 0.0],
+                [0.0, 1.0, 1.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, 0.0],
+            ],
+        );
+
+        assert_eq!(ao(&top, &[]), [3, 3, 3, 3]);
+        // Blocks below or beside the block don't occlude its top.
+        assert_eq!(ao(&top, &[[1, 0, 0], [0, -1, 0]]), [3, 3, 3, 3]);
+
+        // A block next to the top face darkens the two vertices on its side.
+        assert_eq!(ao(&top, &[[1, 1, 0]]), [3, 3, 2, 2]);
+        // A block on the diagonal only darkens the nearest vertex.
+        assert_eq!(ao(&top, &[[1, 1, 1]]), [3, 3, 2, 3]);
+        assert_eq!(ao(&top, &[[1, 1, 0], [1, 1, 1]]), [3, 3, 1, 2]);
+        // An inner corner is fully occluded, no matter the diagonal block.
+        assert_eq!(ao(&top, &[[1, 1, 0], [0, 1, 1]]), [3, 2, 0, 2]);
+
+        // The top of a bottom slab is inside the block, so it is occluded by the blocks around it.
+        let slab_top = quad(
+            Direction::Up,
+            [
+                [0.0, 0.5, 0.0],
+                [0.0, 0.5, 1.0],
+                [1.0, 0.5, 1.0],
+                [1.0, 0.5, 0.0],
+            ],
+        );
+        assert_eq!(ao(&slab_top, &[[-1, 0, 0]]), [2, 2, 3, 3]);
+        assert_eq!(ao(&slab_top, &[[-1, 1, 0]]), [3, 3, 3, 3]);
+
+        let north = quad(
+            Direction::North,
+            [
+                [1.0, 1.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+        );
+        // The ground in front of a wall darkens its bottom.
+        assert_eq!(ao(&north, &[[0, -1, -1]]), [3, 2, 2, 3]);
+    }
+
+    #[test]
+    fn smooth_light_test() {
+        let top = quad(
+            Direction::Up,
+            [
+                [0.0, 1.0, 0.0],
+                [0.0, 1.0, 1.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, 0.0],
+            ],
+        );
+
+        // A torch with block light 14 next to the block above, and a wall on the other side.
+        let light = |offset: glam::IVec3| match offset.to_array() {
+            [1, 1, 0] => (0, 14),
+            [-1, 1, 0] => (0, 0),
+            [0, 1, 0] => (15, 13),
+            _ => (15, 12),
+        };
+        let wall = |offset: glam::IVec3| offset.x == -1;
+
+        assert_eq!(
+            quad_light(&top, light, wall),
+            [
+                // Next to the wall, which counts as the faced block.
+                (15.0, 12.5),
+                (15.0, 12.5),
+                // Next to the torch, which has no sky light.
+                (11.25, 12.75),
+                (11.25, 12.75),
+            ]
+        );
+
+        // A corner blocked off on both sides is not sampled.
+        let corner = |offset: glam::IVec3| match offset.to_array() {
+            [1, 1, 1] => (15, 15),
+            [1, 1, 0] | [0, 1, 1] => (0, 0),
+            _ => (4, 0),
+        };
+        let sides = |offset: glam::IVec3| matches!(offset.to_array(), [1, 1, 0] | [0, 1, 1]);
+        assert_eq!(quad_light(&top, corner, sides)[2], (4.0, 0.0));
+    }
+
+    #[test]
+    fn triangulation_test() {
+        assert_eq!(quad_indices([3, 3, 3, 3]), [0, 1, 2, 0, 2, 3]);
+        // A single dark corner is kept in one triangle instead of darkening a whole diagonal.
+        assert_eq!(quad_indices([0, 3, 3, 3]), [0, 1, 3, 1, 2, 3]);
+        assert_eq!(quad_indices([3, 0, 3, 3]), [0, 1, 2, 0, 2, 3]);
+    }
+}
@@ -0,0 +1,1165 @@
+use std::{
+    array,
+    collections::{HashMap, HashSet},
+    f32::consts::{FRAC_PI_2, PI},
+    sync::Arc,
+};
+
+use azalea_block::{Block, BlockState};
+use azalea_core::{direction::Direction, position::BlockPos};
+use bevy::{ecs::system::Resource, log::*};
+
+use super::{
+    block_state::{BlockRenderState, Variant, VariantDesc},
+    model::{Axis, Rotation},
+    random::{position_seed, LegacyRandom},
+    texture::TextureImages,
+    tint::Tint,
+    BlockModelRef, LoadedAssets, TextureIdMap,
+};
+
+pub const DIRECTIONS: [Direction; 6] = [
+    Direction::Down,
+    Direction::Up,
+    Direction::North,
+    Direction::South,
+    Direction::West,
+    Direction::East,
+];
+
+/// A single textured quad of a block model, in block local coordinates (0..1).
+#[derive(Debug, Clone)]
+pub struct BakedQuad {
+    pub positions: [glam::Vec3; 4],
+    pub uvs: [glam::Vec2; 4],
+    pub texture: usize,
+    pub direction: Direction,
+    pub cullface: Option<Direction>,
+    /// The part of the cullface side this quad covers, the quad is hidden when the neighbour on
+    /// that side occludes all of it.
+    pub cull_mask: FaceMask,
+    pub tint_index: Option<i32>,
+    pub shade: bool,
+}
+
+impl BakedQuad {
+    /// Whether this quad lies on the `direction` side of the block and covers all of it.
+    pub fn covers_face(&self, direction: Direction) -> bool {
+        const EPSILON: f32 = 1e-4;
+
+        let min = self.positions.into_iter().reduce(glam::Vec3::min).unwrap();
+        let max = self.positions.into_iter().reduce(glam::Vec3::max).unwrap();
+
+        let normal = direction_normal(direction);
+        let plane = if normal.max_element() > 0.0 { 1.0 } else { 0.0 };
+
+        (0..3).all(|axis| {
+            if normal[axis] != 0.0 {
+                (min[axis] - plane).abs() < EPSILON && (max[axis] - plane).abs() < EPSILON
+            } else {
+                min[axis] < EPSILON && max[axis] > 1.0 - EPSILON
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BakedModel {
+    pub quads: Vec<BakedQuad>,
+    pub ambient_occlusion: bool,
+    /// The parts of each side, indexed like [`DIRECTIONS`], covered by opaque quads.
+    pub occlusion: [FaceMask; 6],
+    /// The parts of each side, indexed like [`DIRECTIONS`], covered by any quad.
+    pub faces: [FaceMask; 6],
+}
+
+impl BakedModel {
+    /// The parts of each side, indexed like [`DIRECTIONS`], covered by quads with a texture that
+    /// `is_opaque` says is opaque.
+    pub fn occluding_faces(&self, mut is_opaque: impl FnMut(usize) -> bool) -> [FaceMask; 6] {
+        let mut occlusion = [FaceMask::EMPTY; 6];
+
+        for quad in &self.quads {
+            let Some(cullface) = quad.cullface else {
+                continue;
+            };
+
+            if is_opaque(quad.texture) {
+                let i = direction_index(cullface);
+                occlusion[i] = occlusion[i].union(FaceMask::from_quad(quad, cullface, false));
+            }
+        }
+
+        occlusion
+    }
+
+    /// Whether every side of the block is covered by a quad culled by that side.
+    pub fn is_full_cube(&self) -> bool {
+        DIRECTIONS.into_iter().all(|direction| {
+            self.quads
+                .iter()
+                .any(|quad| quad.cullface == Some(direction) && quad.covers_face(direction))
+        })
+    }
+}
+
+/// The blocks that, like vanilla's `LeavesBlock`, keep their faces between two blocks of the same
+/// kind.
+const LEAVES: [&str; 10] = [
+    "oak_leaves",
+    "spruce_leaves",
+    "birch_leaves",
+    "jungle_leaves",
+    "acacia_leaves",
+    "cherry_leaves",
+    "dark_oak_leaves",
+    "mangrove_leaves",
+    "azalea_leaves",
+    "flowering_azalea_leaves",
+];
+
+/// A list of models that one is randomly picked from, like the rotated grass block models.
+#[derive(Debug, Default)]
+pub struct WeightedModel {
+    pub models: Vec<(u32, Arc<BakedModel>)>,
+    pub total_weight: u32,
+}
+
+impl WeightedModel {
+    /// Picks a model the same way vanilla's `WeightedBakedModel` does.
+    pub fn pick(&self, random: &mut LegacyRandom) -> Option<&BakedModel> {
+        if self.models.len() <= 1 || self.total_weight == 0 {
+            return self.models.first().map(|(_, model)| model.as_ref());
+        }
+
+        let mut index = (random.next_long() as i32).wrapping_abs() % self.total_weight as i32;
+        for (weight, model) in &self.models {
+            index -= *weight as i32;
+            if index < 0 {
+                return Some(model);
+            }
+        }
+        None
+    }
+}
+
+/// The models rendered for a single block state. Multipart block states can have several parts.
+#[derive(Debug, Default)]
+pub struct BlockStateModel {
+    pub parts: Vec<WeightedModel>,
+    pub multipart: bool,
+    /// Whether every model this state can pick is a full cube, lets the mesher skip blocks that
+    /// are completely surrounded.
+    pub full_cube: bool,
+    /// The parts of each side, indexed like [`DIRECTIONS`], that hide the faces of neighbours.
+    pub occlusion: [FaceMask; 6],
+    /// The parts of each side, indexed like [`DIRECTIONS`], that hide the faces of neighbours of
+    /// the same block, whether they are opaque or not.
+    pub faces: [FaceMask; 6],
+    pub block: &'static str,
+    /// Transparent blocks like glass and panes hide their faces where they touch a block of the
+    /// same kind.
+    pub cull_same_block: bool,
+    pub tint: Tint,
+}
+
+impl BlockStateModel {
+    pub fn new(
+        block: &'static str,
+        tint: Tint,
+        parts: Vec<WeightedModel>,
+        multipart: bool,
+    ) -> Self {
+        let full_cube = parts.iter().any(|part| {
+            !part.models.is_empty() && part.models.iter().all(|(_, model)| model.is_full_cube())
+        });
+
+        // A side is covered when every model a part can pick covers it.
+        let covered = |faces: fn(&BakedModel) -> &[FaceMask; 6]| {
+            array::from_fn(|i| {
+                parts
+                    .iter()
+                    .filter(|part| !part.models.is_empty())
+                    .map(|part| {
+                        part.models.iter().fold(FaceMask::FULL, |mask, (_, model)| {
+                            mask.intersection(faces(model)[i])
+                        })
+                    })
+                    .fold(FaceMask::EMPTY, FaceMask::union)
+            })
+        };
+
+        Self {
+            cull_same_block: !LEAVES.contains(&block),
+            full_cube,
+            occlusion: covered(|model| &model.occlusion),
+            faces: covered(|model| &model.faces),
+            block,
+            tint,
+            parts,
+            multipart,
+        }
+    }
+
+    /// Whether this is an opaque full cube, the blocks that cast ambient occlusion.
+    pub fn is_opaque_cube(&self) -> bool {
+        self.full_cube && self.occlusion == [FaceMask::FULL; 6]
+    }
+
+    /// Returns the models to render for this state at `pos`. The choice only depends on the
+    /// position, so it matches what players see in the vanilla client.
+    pub fn models(&self, pos: BlockPos) -> impl Iterator<Item = &BakedModel> {
+        let mut seed = position_seed(pos);
+        if self.multipart {
+            seed = LegacyRandom::new(seed).next_long();
+        }
+
+        self.parts
+            .iter()
+            .filter_map(move |part| part.pick(&mut LegacyRandom::new(seed)))
+    }
+}
+
+/// Baked models for every block state, indexed by the block state id.
+#[derive(Debug, Clone, Resource)]
+pub struct BlockStateModels(Arc<Vec<BlockStateModel>>);
+
+impl FromIterator<BlockStateModel> for BlockStateModels {
+    /// Collects the models of every block state, in the order of their ids.
+    fn from_iter<T: IntoIterator<Item = BlockStateModel>>(models: T) -> Self {
+        Self(Arc::new(models.into_iter().collect()))
+    }
+}
+
+impl BlockStateModels {
+    /// Bakes the models of every block state. The textures they use are added to `textures` and
+    /// decoded into `images`, to tell which faces are opaque.
+    pub fn bake(
+        assets: &LoadedAssets,
+        textures: &mut TextureIdMap,
+        images: &mut TextureImages,
+    ) -> Self {
+        let mut baker = ModelBaker {
+            assets,
+            textures,
+            images,
+            cache: HashMap::new(),
+        };
+
+        let mut missing = HashSet::new();
+
+        (0..=BlockState::max_state())
+            .map(|id| {
+                let state = BlockState::try_from(id).expect("id should be a valid block state");
+                let block = Box::<dyn Block>::from(state);
+                let properties = block.property_map();
+
+                let Some(block_state) = assets.get_block_state(&format!("block/{}", block.id()))
+                else {
+                    if missing.insert(block.id()) {
+                        warn!("could not find block state definition for {}", block.id());
+                    }
+                    return BlockStateModel::default();
+                };
+
+                let variants = match block_state {
+                    BlockRenderState::Variants(_) => {
+                        block_state.variant_for(&properties).into_iter().collect()
+                    }
+                    BlockRenderState::MultiPart(_) => {
+                        block_state.multipart_variants_for(&properties)
+                    }
+                };
+
+                let parts = variants
+                    .into_iter()
+                    .map(|variant| baker.bake_weighted(variant))
+                    .collect();
+
+                BlockStateModel::new(
+                    block.id(),
+                    Tint::for_block(block.id(), &properties),
+                    parts,
+                    matches!(block_state, BlockRenderState::MultiPart(_)),
+                )
+            })
+            .collect()
+    }
+
+    pub fn get(&self, state: BlockState) -> &BlockStateModel {
+        &self.0[state.id as usize]
+    }
+}
+
+struct ModelBaker<'a> {
+    assets: &'a LoadedAssets,
+    textures: &'a mut TextureIdMap,
+    images: &'a mut TextureImages,
+    cache: HashMap<(String, ModelRotation, bool), Arc<BakedModel>>,
+}
+
+impl<'a> ModelBaker<'a> {
+    fn bake_weighted(&mut self, variant: &Variant) -> WeightedModel {
+        let models: Vec<_> = variant
+            .descs()
+            .iter()
+            .filter_map(|desc| Some((desc.weight, self.bake(desc)?)))
+            .collect();
+
+        WeightedModel {
+            total_weight: models.iter().map(|(weight, _)| weight).sum(),
+            models,
+        }
+    }
+
+    fn bake(&mut self, desc: &VariantDesc) -> Option<Arc<BakedModel>> {
+        let rotation = ModelRotation::new(desc.x_rotation, desc.y_rotation);
+        let key = (desc.model.clone(), rotation, desc.uvlock);
+        if let Some(model) = self.cache.get(&key) {
+            return Some(model.clone());
+        }
+
+        let Some(block_model) = self.assets.get_block_model(&desc.model) else {
+            error!("could not find block model {}", desc.model);
+            return None;
+        };
+
+        let mut model = bake_model(&block_model, rotation, desc.uvlock, self.textures);
+        self.images.load(self.assets, self.textures);
+        model.occlusion = model.occluding_faces(|texture| self.images.is_opaque(texture));
+
+        let model = Arc::new(model);
+        self.cache.insert(key, model.clone());
+        Some(model)
+    }
+}
+
+pub fn bake_model(
+    block_model: &BlockModelRef,
+    rotation: ModelRotation,
+    uvlock: bool,
+    textures: &mut TextureIdMap,
+) -> BakedModel {
+    let mut quads = Vec::new();
+
+    for cube in block_model.elements().into_iter().flatten() {
+        for direction in DIRECTIONS {
+            let Some(face) = cube.faces.get(direction) else {
+                continue;
+            };
+
+            let Some(texture) = block_model.get_texture(&face.texture) else {
+                continue;
+            };
+
+            // With uvlock the texture stays aligned to the world instead of turning with the
+            // model.
+            let (uv, uv_rotation) = if uvlock {
+                let uv = face
+                    .uv
+                    .unwrap_or_else(|| default_uv(direction, cube.from, cube.to));
+                let (uv, uv_rotation) = lock_uv(uv, face.rotation, direction, rotation);
+                (Some(uv), uv_rotation)
+            } else {
+                (face.uv, face.rotation)
+            };
+            let uvs = generate_uv(direction, uv, uv_rotation, cube.from, cube.to);
+
+            let positions =
+                face_vertices(direction, cube.from / 16.0, cube.to / 16.0).map(|position| {
+                    let position = match &cube.rotation {
+                        Some(element_rotation) => rotate_element(element_rotation, position),
+                        None => position,
+                    };
+                    rotation.rotate_point(position)
+                });
+
+            // Rotated elements can end up facing in between two directions, vanilla uses the
+            // nearest one to the actual normal for shading.
+            let normal = (positions[2] - positions[1]).cross(positions[0] - positions[1]);
+            let direction = if normal.length_squared() > f32::EPSILON {
+                nearest_direction(normal)
+            } else {
+                rotation.rotate_direction(direction)
+            };
+
+            let cullface = face
+                .cullface
+                .as_deref()
+                .and_then(parse_direction)
+                .map(|cullface| rotation.rotate_direction(cullface));
+
+            let mut quad = BakedQuad {
+                positions,
+                uvs,
+                texture: textures.get_or_insert(&texture),
+                direction,
+                cullface,
+                cull_mask: FaceMask::EMPTY,
+                tint_index: face.tintindex,
+                shade: cube.shade,
+            };
+            if let Some(cullface) = cullface {
+                quad.cull_mask = FaceMask::from_quad(&quad, cullface, true);
+            }
+            quads.push(quad);
+        }
+    }
+
+    let mut model = BakedModel {
+        quads,
+        ambient_occlusion: block_model.ambient_occlusion,
+        occlusion: [FaceMask::EMPTY; 6],
+        faces: [FaceMask::EMPTY; 6],
+    };
+    model.faces = model.occluding_faces(|_| true);
+    model
+}
+
+/// Which sixteenths of a side of the block are covered, one row of bits for every sixteenth.
+/// Rows and columns follow the two axes that are not the axis of the side, in x, y, z order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaceMask([u16; 16]);
+
+impl FaceMask {
+    pub const EMPTY: FaceMask = FaceMask([0; 16]);
+    pub const FULL: FaceMask = FaceMask([u16::MAX; 16]);
+
+    /// Projects `quad` onto the `direction` side of the block. With `outward` every sixteenth
+    /// the quad touches is included, otherwise only the ones it covers completely.
+    pub fn from_quad(quad: &BakedQuad, direction: Direction, outward: bool) -> Self {
+        let min = quad.positions.into_iter().reduce(glam::Vec3::min).unwrap() * 16.0;
+        let max = quad.positions.into_iter().reduce(glam::Vec3::max).unwrap() * 16.0;
+
+        let (row_axis, column_axis) = match direction {
+            Direction::Down | Direction::Up => (0, 2),
+            Direction::North | Direction::South => (0, 1),
+            Direction::West | Direction::East => (1, 2),
+        };
+
+        let range = |axis: usize| {
+            let (start, end) = if outward {
+                ((min[axis] + 1e-3).floor(), (max[axis] - 1e-3).ceil())
+            } else {
+                ((min[axis] - 1e-3).ceil(), (max[axis] + 1e-3).floor())
+            };
+            start.clamp(0.0, 16.0) as usize..end.clamp(0.0, 16.0) as usize
+        };
+
+        let columns = range(column_axis);
+        let bits = if columns.is_empty() {
+            0
+        } else {
+            (u16::MAX >> (16 - columns.len())) << columns.start
+        };
+
+        let mut mask = Self::EMPTY;
+        for row in range(row_axis) {
+            mask.0[row] = bits;
+        }
+        mask
+    }
+
+    pub fn union(self, other: FaceMask) -> FaceMask {
+        FaceMask(array::from_fn(|i| self.0[i] | other.0[i]))
+    }
+
+    pub fn intersection(self, other: FaceMask) -> FaceMask {
+        FaceMask(array::from_fn(|i| self.0[i] & other.0[i]))
+    }
+
+    pub fn is_covered_by(self, other: FaceMask) -> bool {
+        self.intersection(other) == self
+    }
+}
+
+/// Applies the rotation of a model element to `position`. With `rescale` the element is stretched
+/// along the other two axes so it still spans the whole block, like the planes of cross models.
+pub fn rotate_element(rotation: &Rotation, position: glam::Vec3) -> glam::Vec3 {
+    let (axis, scale) = match rotation.axis {
+        Axis::X => (glam::Vec3::X, glam::Vec3::new(0.0, 1.0, 1.0)),
+        Axis::Y => (glam::Vec3::Y, glam::Vec3::new(1.0, 0.0, 1.0)),
+        Axis::Z => (glam::Vec3::Z, glam::Vec3::new(1.0, 1.0, 0.0)),
+    };
+
+    let scale = if rotation.rescale {
+        scale * (1.0 / rotation.angle.abs().to_radians().cos() - 1.0) + 1.0
+    } else {
+        glam::Vec3::ONE
+    };
+
+    let origin = rotation.origin / 16.0;
+    let quat = glam::Quat::from_axis_angle(axis, rotation.angle.to_radians());
+    (quat * (position - origin)) * scale + origin
+}
+
+/// The rotation of a block state variant in 90 degree steps. Like in vanilla the x rotation is
+/// applied first, both rotate around the center of the block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ModelRotation {
+    x: u8,
+    y: u8,
+}
+
+impl ModelRotation {
+    pub fn new(x_rotation: i32, y_rotation: i32) -> Self {
+        Self {
+            x: (x_rotation.rem_euclid(360) / 90) as u8,
+            y: (y_rotation.rem_euclid(360) / 90) as u8,
+        }
+    }
+
+    /// Rotates a vector relative to the center of the block.
+    pub fn rotate(self, mut v: glam::Vec3) -> glam::Vec3 {
+        for _ in 0..self.x {
+            v = glam::Vec3::new(v.x, v.z, -v.y);
+        }
+        for _ in 0..self.y {
+            v = glam::Vec3::new(-v.z, v.y, v.x);
+        }
+        v
+    }
+
+    /// The rotation as a quaternion, vanilla's `BlockModelRotation::rotation`.
+    pub fn quat(self) -> glam::Quat {
+        glam::Quat::from_rotation_y(-(self.y as f32) * FRAC_PI_2)
+            * glam::Quat::from_rotation_x(-(self.x as f32) * FRAC_PI_2)
+    }
+
+    pub fn rotate_point(self, position: glam::Vec3) -> glam::Vec3 {
+        self.rotate(position - 0.5) + 0.5
+    }
+
+    pub fn rotate_direction(self, direction: Direction) -> Direction {
+        nearest_direction(self.rotate(direction_normal(direction)))
+    }
+}
+
+pub fn direction_index(direction: Direction) -> usize {
+    match direction {
+        Direction::Down => 0,
+        Direction::Up => 1,
+        Direction::North => 2,
+        Direction::South => 3,
+        Direction::West => 4,
+        Direction::East => 5,
+    }
+}
+
+pub fn opposite_direction(direction: Direction) -> Direction {
+    match direction {
+        Direction::Down => Direction::Up,
+        Direction::Up => Direction::Down,
+        Direction::North => Direction::South,
+        Direction::South => Direction::North,
+        Direction::West => Direction::East,
+        Direction::East => Direction::West,
+    }
+}
+
+pub fn direction_normal(direction: Direction) -> glam::Vec3 {
+    match direction {
+        Direction::Down => glam::Vec3::NEG_Y,
+        Direction::Up => glam::Vec3::Y,
+        Direction::North => glam::Vec3::NEG_Z,
+        Direction::South => glam::Vec3::Z,
+        Direction::West => glam::Vec3::NEG_X,
+        Direction::East => glam::Vec3::X,
+    }
+}
+
+/// Returns the direction closest to `normal`, preferring the first of [`DIRECTIONS`] on ties
+/// like vanilla does.
+pub fn nearest_direction(normal: glam::Vec3) -> Direction {
+    let mut nearest = DIRECTIONS[0];
+    for direction in DIRECTIONS {
+        if direction_normal(direction).dot(normal) > direction_normal(nearest).dot(normal) {
+            nearest = direction;
+        }
+    }
+    nearest
+}
+
+pub fn parse_direction(s: &str) -> Option<Direction> {
+    match s {
+        "down" | "bottom" => Some(Direction::Down),
+        "up" => Some(Direction::Up),
+        "north" => Some(Direction::North),
+        "south" => Some(Direction::South),
+        "west" => Some(Direction::West),
+        "east" => Some(Direction::East),
+        _ => None,
+    }
+}
+
+/// Returns the corners of a face of the box spanning `from`..`to`, in counter clockwise order
+/// starting from the corner that gets the top left of the texture. This is the same order
+/// vanilla uses.
+pub fn face_vertices(direction: Direction, from: glam::Vec3, to: glam::Vec3) -> [glam::Vec3; 4] {
+    let corners = match direction {
+        Direction::Down => [[0, 0, 1], [0, 0, 0], [1, 0, 0], [1, 0, 1]],
+        Direction::Up => [[0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]],
+        Direction::North => [[1, 1, 0], [1, 0, 0], [0, 0, 0], [0, 1, 0]],
+        Direction::South => [[0, 1, 1], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+        Direction::West => [[0, 1, 0], [0, 0, 0], [0, 0, 1], [0, 1, 1]],
+        Direction::East => [[1, 1, 1], [1, 0, 1], [1, 0, 0], [1, 1, 0]],
+    };
+
+    corners.map(|[x, y, z]| {
+        glam::Vec3::new(
+            if x == 0 { from.x } else { to.x },
+            if y == 0 { from.y } else { to.y },
+            if z == 0 { from.z } else { to.z },
+        )
+    })
+}
+
+/// Turns the uv rectangle and rotation of a face facing `direction` so its texture keeps the
+/// same orientation in the world once the model is rotated, like vanilla's
+/// `FaceBakery::recomputeUVs`.
+pub fn lock_uv(
+    uv: [f32; 4],
+    uv_rotation: i32,
+    direction: Direction,
+    rotation: ModelRotation,
+) -> ([f32; 4], i32) {
+    // Rotations from the uv space of a face, where the south face is the identity, to the block.
+    let face_to_block = |direction| match direction {
+        Direction::Down => glam::Quat::from_rotation_x(FRAC_PI_2),
+        Direction::Up => glam::Quat::from_rotation_x(-FRAC_PI_2),
+        Direction::North => glam::Quat::from_rotation_y(PI),
+        Direction::South => glam::Quat::IDENTITY,
+        Direction::West => glam::Quat::from_rotation_y(-FRAC_PI_2),
+        Direction::East => glam::Quat::from_rotation_y(FRAC_PI_2),
+    };
+
+    let quat = face_to_block(direction).inverse()
+        * rotation.quat().inverse()
+        * face_to_block(rotation.rotate_direction(direction));
+    // Every rotation is in quarter turns, so rounding only removes float error.
+    let matrix = glam::Mat3::from_quat(quat);
+    let matrix = glam::Mat3::from_cols(
+        matrix.x_axis.round(),
+        matrix.y_axis.round(),
+        matrix.z_axis.round(),
+    );
+    let center = glam::Vec3::splat(0.5);
+    let transform = glam::Mat4::from_translation(center)
+        * glam::Mat4::from_mat3(matrix)
+        * glam::Mat4::from_translation(-center);
+
+    // Like vanilla's `getReverseIndex`, the two corners are read from the vertices the face's
+    // rotation turned them to, so they are the corners of the rectangle before its rotation.
+    let vertex_uvs = face_uvs(uv, uv_rotation);
+    let shift = uv_rotation.rem_euclid(360) as usize / 90;
+    let reverse_index = |vertex: usize| (vertex + 4 - shift) % 4;
+    let (first, third) = (
+        vertex_uvs[reverse_index(0)] * 16.0,
+        vertex_uvs[reverse_index(2)] * 16.0,
+    );
+
+    let corner = |uv: glam::Vec2| {
+        let corner = transform.transform_point3((uv / 16.0).extend(0.0));
+        (corner.x * 16.0, corner.y * 16.0)
+    };
+    let (u1, v1) = corner(first);
+    let (u2, v2) = corner(third);
+
+    // The rectangle keeps the direction it was flipped in on each axis.
+    let sign = |x: f32| {
+        if x > 0.0 {
+            1
+        } else if x < 0.0 {
+            -1
+        } else {
+            0
+        }
+    };
+    let (u1, u2) = if sign(third.x - first.x) == sign(u2 - u1) {
+        (u1, u2)
+    } else {
+        (u2, u1)
+    };
+    let (v1, v2) = if sign(third.y - first.y) == sign(v2 - v1) {
+        (v1, v2)
+    } else {
+        (v2, v1)
+    };
+
+    let angle = (uv_rotation as f32).to_radians();
+    let turned = transform.transform_vector3(glam::Vec3::new(angle.cos(), angle.sin(), 0.0));
+    let quarters = (turned.y.atan2(turned.x).to_degrees() / 90.0).round() as i32;
+
+    ([u1, v1, u2, v2], (-quarters * 90).rem_euclid(360))
+}
+
+/// The uv rectangle of a face without one, the face's part of the texture as if the texture was
+/// wrapped around the whole block.
+pub fn default_uv(direction: Direction, from: glam::Vec3, to: glam::Vec3) -> [f32; 4] {
+    match direction {
+        Direction::Down => [from.x, 16.0 - to.z, to.x, 16.0 - from.z],
+        Direction::Up => [from.x, from.z, to.x, to.z],
+        Direction::North => [16.0 - to.x, 16.0 - to.y, 16.0 - from.x, 16.0 - from.y],
+        Direction::South => [from.x, 16.0 - to.y, to.x, 16.0 - from.y],
+        Direction::West => [from.z, 16.0 - to.y, to.z, 16.0 - from.y],
+        Direction::East => [16.0 - to.z, 16.0 - to.y, 16.0 - from.z, 16.0 - from.y],
+    }
+}
+
+/// Returns the uv of every corner of a face, in the order of [`face_vertices`]. `from` and `to`
+/// are the bounds of the element, used for the default uvs when the face has none.
+///
+/// Like in vanilla the uvs are used as is, so a reversed rectangle (`u1 > u2`) mirrors the
+/// texture, and `rotation` turns the texture clockwise on the face.
+pub fn generate_uv(
+    direction: Direction,
+    uvs: Option<[f32; 4]>,
+    rotation: i32,
+    from: glam::Vec3,
+    to: glam::Vec3,
+) -> [glam::Vec2; 4] {
+    face_uvs(
+        uvs.unwrap_or_else(|| default_uv(direction, from, to)),
+        rotation,
+    )
+}
+
+/// Returns the uv of every corner of a face with the uv rectangle `uv` turned by `rotation`,
+/// vanilla's `BlockFaceUV::getU` and `getV`.
+fn face_uvs(uv: [f32; 4], rotation: i32) -> [glam::Vec2; 4] {
+    let [u1, v1, u2, v2] = uv.map(|c| c / 16.0);
+
+    let corners = [
+        glam::Vec2::new(u1, v1),
+        glam::Vec2::new(u1, v2),
+        glam::Vec2::new(u2, v2),
+        glam::Vec2::new(u2, v1),
+    ];
+
+    let shift = rotation.rem_euclid(360) as usize / 90;
+    [0, 1, 2, 3].map(|i| corners[(i + shift) % 4])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use azalea_block::{Block, BlockState};
+    use azalea_core::direction::Direction;
+
+    use super::{
+        bake_model, generate_uv, lock_uv, BakedModel, BlockStateModels, FaceMask, ModelRotation,
+    };
+    use crate::renderer::assets::{
+        block_state::BlockRenderState, model::BlockModel, texture::TextureImages, BlockModelRef,
+        LoadedAssets, TextureIdMap,
+    };
+
+    fn bake(json: &str, rotation: ModelRotation, uvlock: bool) -> BakedModel {
+        let model = BlockModel::from_str(json).unwrap();
+
+        let model = BlockModelRef {
+            ambient_occlusion: model.ambientocclusion.unwrap_or(true),
+            parent: None,
+            textures: &model.textures,
+            elements: &model.elements,
+        };
+
+        bake_model(&model, rotation, uvlock, &mut TextureIdMap::default())
+    }
+
+    fn assert_near(a: glam::Vec3, b: glam::Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+    }
+
+    /// Finds the block state of the block `id` with `properties`.
+    fn find_state(id: &str, properties: &[(&str, &str)]) -> BlockState {
+        (0..=BlockState::max_state())
+            .filter_map(|state| BlockState::try_from(state).ok())
+            .find(|state| {
+                let block = Box::<dyn Block>::from(*state);
+                let block_properties = block.property_map();
+                block.id() == id
+                    && properties
+                        .iter()
+                        .all(|(name, value)| block_properties.get(name) == Some(value))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn state_table() {
+        let model = |texture: &str| {
+            BlockModel::from_str(&format!(
+                r##"
+{{
+    "textures": {{ "all": "block/{texture}" }},
+    "elements": [
+        {{  "from": [ 0, 0, 0 ],
+            "to": [ 16, 16, 16 ],
+            "faces": {{ "north": {{ "texture": "#all", "cullface": "north" }} }}
+        }}
+    ]
+}}
+            "##
+            ))
+            .unwrap()
+        };
+        let assets = LoadedAssets {
+            block_models: HashMap::from([
+                ("block/stone".to_owned(), model("stone")),
+                ("block/furnace".to_owned(), model("furnace_front")),
+                ("block/furnace_on".to_owned(), model("furnace_front_on")),
+            ]),
+            block_states: HashMap::from([
+                (
+                    "block/stone".to_owned(),
+                    BlockRenderState::from_str(
+                        r#"{ "variants": { "": { "model": "block/stone" } } }"#,
+                    )
+                    .unwrap(),
+                ),
+                (
+                    "block/furnace".to_owned(),
+                    BlockRenderState::from_str(
+                        r#"{
+    "variants": {
+        "facing=east,lit=false": { "model": "block/furnace", "y": 90 },
+        "facing=east,lit=true": { "model": "block/furnace_on", "y": 90 },
+        "facing=north,lit=false": { "model": "block/furnace" },
+        "facing=north,lit=true": { "model": "block/furnace_on" }
+    }
+}"#,
+                    )
+                    .unwrap(),
+                ),
+            ]),
+            texture_path: PathBuf::new(),
+        };
+
+        let mut textures = TextureIdMap::default();
+        let models = BlockStateModels::bake(&assets, &mut textures, &mut TextureImages::default());
+
+        // The texture and direction of the only quad of a state's model.
+        let quad = |id: &str, properties: &[(&str, &str)]| {
+            let model = models.get(find_state(id, properties));
+            let quad = &model.parts[0].models[0].1.quads[0];
+            (textures.name(quad.texture).to_owned(), quad.direction)
+        };
+
+        assert_eq!(
+            quad("stone", &[]),
+            ("block/stone".to_owned(), Direction::North)
+        );
+        assert_eq!(
+            quad("furnace", &[("facing", "north"), ("lit", "true")]),
+            ("block/furnace_front_on".to_owned(), Direction::North)
+        );
+        assert_eq!(
+            quad("furnace", &[("facing", "east"), ("lit", "false")]),
+            ("block/furnace_front".to_owned(), Direction::East)
+        );
+        // Blocks without a blockstate definition have nothing to render.
+        assert!(models.get(BlockState::AIR).parts.is_empty());
+        assert!(models.get(find_state("glass", &[])).parts.is_empty());
+    }
+
+    #[test]
+    fn variant_rotation() {
+        let baked = bake(
+            r##"
+{
+    "textures": { "all": "block/stone" },
+    "elements": [
+        {   "from": [ 0, 0, 0 ],
+            "to": [ 16, 16, 8 ],
+            "faces": {
+                "north": { "texture": "#all", "cullface": "north" },
+                "up":    { "uv": [ 0, 0, 16, 8 ], "texture": "#all" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::new(0, 90),
+            true,
+        );
+        assert!(!baked.is_full_cube());
+        assert!(baked.quads[1].covers_face(Direction::East));
+        assert!(!baked.quads[0].covers_face(Direction::Up));
+        assert_eq!(baked.quads[1].direction, Direction::East);
+        assert_eq!(baked.quads[1].cullface, Some(Direction::East));
+        assert_eq!(baked.quads[0].direction, Direction::Up);
+        assert_eq!(
+            baked.quads[0].positions,
+            [
+                [1.0, 1.0, 0.0],
+                [0.5, 1.0, 0.0],
+                [0.5, 1.0, 1.0],
+                [1.0, 1.0, 1.0]
+            ]
+            .map(Into::into)
+        );
+        assert_eq!(
+            baked.quads[0].uvs,
+            [[1.0, 0.0], [0.5, 0.0], [0.5, 1.0], [1.0, 1.0]].map(Into::into)
+        );
+    }
+
+    #[test]
+    fn face_uv() {
+        let from = glam::Vec3::new(0.0, 0.0, 0.0);
+        let to = glam::Vec3::new(16.0, 8.0, 16.0);
+
+        // The default uvs of a bottom slab's side only cover the lower half of the texture.
+        assert_eq!(
+            generate_uv(Direction::North, None, 0, from, to),
+            [[0.0, 0.5], [0.0, 1.0], [1.0, 1.0], [1.0, 0.5]].map(Into::into)
+        );
+        assert_eq!(
+            generate_uv(Direction::North, None, 90, from, to),
+            [[0.0, 1.0], [1.0, 1.0], [1.0, 0.5], [0.0, 0.5]].map(Into::into)
+        );
+        assert_eq!(
+            generate_uv(Direction::Up, Some([16.0, 0.0, 0.0, 16.0]), 0, from, to),
+            [[1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]].map(Into::into)
+        );
+    }
+
+    #[test]
+    fn uvlock() {
+        let uv = [0.0, 0.0, 4.0, 16.0];
+
+        // A side turned around the y axis keeps its uvs.
+        assert_eq!(
+            lock_uv(uv, 0, Direction::North, ModelRotation::new(0, 90)),
+            (uv, 0)
+        );
+        // The top turned to face north is turned upside down to stay aligned with the world.
+        assert_eq!(
+            lock_uv(uv, 0, Direction::Up, ModelRotation::new(90, 0)),
+            ([12.0, 0.0, 16.0, 16.0], 180)
+        );
+        // The top turned around the y axis has its texture turned back.
+        assert_eq!(
+            lock_uv(
+                [0.0, 0.0, 16.0, 8.0],
+                0,
+                Direction::Up,
+                ModelRotation::new(0, 90)
+            ),
+            ([8.0, 0.0, 16.0, 16.0], 270)
+        );
+
+        // The face's own rotation turns the corners before the lock, and is turned with it.
+        assert_eq!(
+            lock_uv(uv, 90, Direction::Up, ModelRotation::new(90, 0)),
+            ([12.0, 0.0, 16.0, 16.0], 90)
+        );
+        assert_eq!(
+            lock_uv(
+                [0.0, 0.0, 16.0, 8.0],
+                90,
+                Direction::Up,
+                ModelRotation::new(0, 90)
+            ),
+            ([8.0, 0.0, 16.0, 16.0], 180)
+        );
+        assert_eq!(
+            lock_uv(uv, 90, Direction::North, ModelRotation::new(0, 90)),
+            (uv, 270)
+        );
+        let baked = bake(
+            r##"
+{
+    "textures": { "texture": "block/oak_trapdoor" },
+    "elements": [
+        {   "from": [ 0, 0, 0 ],
+            "to": [ 16, 3, 16 ],
+            "faces": {
+                "up": { "uv": [ 0, 0, 4, 16 ], "rotation": 90, "texture": "#texture" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::new(90, 0),
+            true,
+        );
+        assert_eq!(baked.quads[0].direction, Direction::North);
+        assert_eq!(
+            baked.quads[0].uvs,
+            [[0.75, 1.0], [1.0, 1.0], [1.0, 0.0], [0.75, 0.0]].map(Into::into)
+        );
+
+        // The side of a fence post only uses part of the texture, which uvlock keeps.
+        let baked = bake(
+            r##"
+{
+    "textures": { "texture": "block/oak_planks" },
+    "elements": [
+        {   "from": [ 6, 0, 6 ],
+            "to": [ 10, 16, 10 ],
+            "faces": {
+                "north": { "uv": [ 0, 0, 4, 16 ], "texture": "#texture" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::new(0, 90),
+            true,
+        );
+        assert_eq!(baked.quads[0].direction, Direction::East);
+        assert_eq!(
+            baked.quads[0].uvs,
+            [[0.0, 0.0], [0.0, 1.0], [0.25, 1.0], [0.25, 0.0]].map(Into::into)
+        );
+    }
+
+    #[test]
+    fn cross_model() {
+        let baked = bake(
+            r##"
+{
+    "ambientocclusion": false,
+    "textures": {
+        "particle": "#cross",
+        "cross": "block/poppy"
+    },
+    "elements": [
+        {   "from": [ 0.8, 0, 8 ],
+            "to": [ 15.2, 16, 8 ],
+            "rotation": { "origin": [ 8, 8, 8 ], "axis": "y", "angle": 45, "rescale": true },
+            "shade": false,
+            "faces": {
+                "north": { "uv": [ 0, 0, 16, 16 ], "texture": "#cross" },
+                "south": { "uv": [ 0, 0, 16, 16 ], "texture": "#cross" }
+            }
+        },
+        {   "from": [ 8, 0, 0.8 ],
+            "to": [ 8, 16, 15.2 ],
+            "rotation": { "origin": [ 8, 8, 8 ], "axis": "y", "angle": 45, "rescale": true },
+            "shade": false,
+            "faces": {
+                "west": { "uv": [ 0, 0, 16, 16 ], "texture": "#cross" },
+                "east": { "uv": [ 0, 0, 16, 16 ], "texture": "#cross" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::default(),
+            false,
+        );
+
+        assert_eq!(baked.quads.len(), 4);
+
+        // Rescaling stretches the planes from corner to corner of the block.
+        let north = &baked.quads[0].positions;
+        assert_near(north[0], glam::Vec3::new(0.95, 1.0, 0.05));
+        assert_near(north[2], glam::Vec3::new(0.05, 0.0, 0.95));
+
+        let west = &baked.quads[2].positions;
+        assert_near(west[0], glam::Vec3::new(0.05, 1.0, 0.05));
+        assert_near(west[2], glam::Vec3::new(0.95, 0.0, 0.95));
+    }
+
+    #[test]
+    fn wall_torch_model() {
+        let baked = bake(
+            r##"
+{
+    "ambientocclusion": false,
+    "textures": {
+        "particle": "#torch",
+        "torch": "block/torch"
+    },
+    "elements": [
+        {   "from": [ -1, 3.5, 7 ],
+            "to": [ 1, 13.5, 9 ],
+            "rotation": { "origin": [ 0, 3.5, 8 ], "axis": "z", "angle": -22.5 },
+            "shade": false,
+            "faces": {
+                "down": { "uv": [ 7, 13, 9, 15 ], "texture": "#torch" },
+                "up":   { "uv": [ 7,  6, 9,  8 ], "texture": "#torch" }
+            }
+        },
+        {   "from": [ -1, 3.5, 0 ],
+            "to": [ 1, 19.5, 16 ],
+            "rotation": { "origin": [ 0, 3.5, 8 ], "axis": "z", "angle": -22.5 },
+            "shade": false,
+            "faces": {
+                "west": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" },
+                "east": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" }
+            }
+        },
+        {   "from": [ -8, 3.5, 7 ],
+            "to": [ 8, 19.5, 9 ],
+            "rotation": { "origin": [ 0, 3.5, 8 ], "axis": "z", "angle": -22.5 },
+            "shade": false,
+            "faces": {
+                "north": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" },
+                "south": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::new(0, 90),
+            false,
+        );
+
+        // The torch leans away from the wall it is attached to, after the variant rotation the
+        // wall is on the north side.
+        let up = &baked.quads[1];
+        assert_eq!(up.direction, Direction::Up);
+        let center = up.positions.iter().sum::<glam::Vec3>() / 4.0;
+        assert_near(center, glam::Vec3::new(0.5, 0.79617, 0.23918));
+
+        // The base of the stick is not moved by the rotation.
+        let down = &baked.quads[0];
+        let center = down.positions.iter().sum::<glam::Vec3>() / 4.0;
+        assert_near(center, glam::Vec3::new(0.5, 0.21875, 0.0));
+    }
+
+    #[test]
+    fn slab_face_mask() {
+        let baked = bake(
+            r##"
+{
+    "textures": { "side": "block/stone" },
+    "elements": [
+        {   "from": [ 0, 0, 0 ],
+            "to": [ 16, 8, 16 ],
+            "faces": {
+                "down":  { "texture": "#side", "cullface": "down" },
+                "north": { "texture": "#side", "cullface": "north" }
+            }
+        }
+    ]
+}
+        "##,
+            ModelRotation::new(0, 0),
+            false,
+        );
+
+        let down = &baked.quads[0];
+        let north = &baked.quads[1];
+        assert_eq!(down.cull_mask, FaceMask::FULL);
+
+        // The side of a bottom slab covers the lower half of the block's north side.
+        let mut lower_half = FaceMask::EMPTY;
+        for row in 0..16 {
+            lower_half.0[row] = 0x00ff;
+        }
+        assert_eq!(north.cull_mask, lower_half);
+        assert_eq!(
+            FaceMask::from_quad(north, Direction::North, false),
+            lower_half
+        );
+
+        assert!(north.cull_mask.is_covered_by(FaceMask::FULL));
+        assert!(!FaceMask::FULL.is_covered_by(north.cull_mask));
+    }
+}
@@ -1,4 +1,6 @@
-use serde::{de::IgnoredAny, Deserializer};
+use std::{collections::HashMap, fmt};
+
+use serde::{de::Visitor, Deserializer};
 
 #[derive(serde::Deserialize, Debug)]
 pub enum BlockRenderState {
@@ -6,22 +8,158 @@ pub enum BlockRenderState {
     #[serde(with = "tuple_vec_map")]
     Variants(Vec<(String, Variant)>),
 
-    #[serde(rename = "multipart", deserialize_with = "deserialize_multipart")]
-    MultiPart,
-}
-
-fn deserialize_multipart<'de, D>(data: D) -> Result<(), D::Error>
-where
-    D: Deserializer<'de>,
-{
-    data.deserialize_ignored_any(IgnoredAny)?;
-    Ok(())
+    #[serde(rename = "multipart")]
+    MultiPart(Vec<MultiPartCase>),
 }
 
 impl BlockRenderState {
     pub fn from_str(s: &str) -> serde_json::Result<Self> {
         serde_json::from_str(s)
     }
+
+    /// Returns the variant selected for a block with `properties`, or `None` for multipart block
+    /// states and states that no variant key matches.
+    pub fn variant_for(&self, properties: &HashMap<&str, &str>) -> Option<&Variant> {
+        match self {
+            // Like vanilla, later keys take precedence over earlier ones when several match.
+            BlockRenderState::Variants(variants) => variants
+                .iter()
+                .rev()
+                .find(|(key, _)| variant_key_matches(key, properties))
+                .map(|(_, variant)| variant),
+            BlockRenderState::MultiPart(_) => None,
+        }
+    }
+
+    /// Returns the variants of every multipart case that applies to a block with `properties`, in
+    /// the order they appear in the blockstate file. Returns an empty list for variant based block
+    /// states.
+    pub fn multipart_variants_for(&self, properties: &HashMap<&str, &str>) -> Vec<&Variant> {
+        match self {
+            BlockRenderState::Variants(_) => Vec::new(),
+            BlockRenderState::MultiPart(cases) => cases
+                .iter()
+                .filter(|case| case.applies(properties))
+                .map(|case| &case.apply)
+                .collect(),
+        }
+    }
+}
+
+/// Parses a variant key like `facing=north,half=top` into its property predicates. The empty key
+/// has no predicates and matches every state.
+pub fn parse_variant_key(key: &str) -> impl Iterator<Item = (&str, &str)> {
+    key.split(',')
+        .filter(|predicate| !predicate.is_empty())
+        .map(|predicate| predicate.split_once('=').unwrap_or((predicate, "")))
+}
+
+/// Keys may leave out properties, those properties then match any value.
+pub fn variant_key_matches(key: &str, properties: &HashMap<&str, &str>) -> bool {
+    parse_variant_key(key).all(|(name, value)| properties.get(name) == Some(&value))
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct MultiPartCase {
+    pub when: Option<Condition>,
+    pub apply: Variant,
+}
+
+impl MultiPartCase {
+    pub fn applies(&self, properties: &HashMap<&str, &str>) -> bool {
+        self.when
+            .as_ref()
+            .map_or(true, |when| when.matches(properties))
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Condition {
+    Or {
+        #[serde(rename = "OR")]
+        or: Vec<Condition>,
+    },
+    And {
+        #[serde(rename = "AND")]
+        and: Vec<Condition>,
+    },
+    Properties(#[serde(with = "tuple_vec_map")] Vec<(String, PropertyValues)>),
+}
+
+impl Condition {
+    pub fn matches(&self, properties: &HashMap<&str, &str>) -> bool {
+        match self {
+            Condition::Or { or } => or.iter().any(|c| c.matches(properties)),
+            Condition::And { and } => and.iter().all(|c| c.matches(properties)),
+            Condition::Properties(values) => values.iter().all(|(name, values)| {
+                properties
+                    .get(name.as_str())
+                    .is_some_and(|value| values.matches(value))
+            }),
+        }
+    }
+}
+
+/// A `|` separated list of property values, optionally negated with a leading `!`.
+#[derive(Debug, PartialEq)]
+pub struct PropertyValues {
+    pub negate: bool,
+    pub values: Vec<String>,
+}
+
+impl PropertyValues {
+    pub fn parse(s: &str) -> Self {
+        let (negate, s) = match s.strip_prefix('!') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+
+        Self {
+            negate,
+            values: s.split('|').map(str::to_owned).collect(),
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        self.values.iter().any(|v| v == value) != self.negate
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for PropertyValues {
+    fn deserialize<D>(data: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PropertyValuesVisitor;
+
+        impl<'de> Visitor<'de> for PropertyValuesVisitor {
+            type Value = PropertyValues;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a property value")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(PropertyValues::parse(v))
+            }
+
+            // Some resource packs write booleans and numbers without quotes.
+            fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(PropertyValues::parse(&v.to_string()))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(PropertyValues::parse(&v.to_string()))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(PropertyValues::parse(&v.to_string()))
+            }
+        }
+
+        data.deserialize_any(PropertyValuesVisitor)
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -37,6 +175,19 @@ pub enum Variant {
     Array(Vec<VariantDesc>),
 }
 
+impl Variant {
+    pub fn descs(&self) -> &[VariantDesc] {
+        match self {
+            Variant::Single(desc) => std::slice::from_ref(desc),
+            Variant::Array(descs) => descs,
+        }
+    }
+
+    pub fn first(&self) -> Option<&VariantDesc> {
+        self.descs().first()
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct VariantDesc {
     pub model: String,
@@ -50,11 +201,21 @@ pub struct VariantDesc {
     pub y_rotation: i32,
 
     #[serde(default)]
-    pub ublock: bool,
+    pub uvlock: bool,
+
+    /// How likely this variant is to be picked from a list of variants.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::BlockRenderState;
 
     #[test]
@@ -76,4 +237,96 @@ mod tests {
             .unwrap();
         }
     }
+
+    #[test]
+    fn variant_key_test() {
+        let state = BlockRenderState::from_str(
+            r#"{
+    "variants": {
+        "": { "model": "block/furnace" },
+        "facing=east,lit=false": { "model": "block/furnace", "y": 90 },
+        "facing=east,lit=true": { "model": "block/furnace_on", "y": 90 },
+        "lit=true": { "model": "block/furnace_on" }
+    }
+}"#,
+        )
+        .unwrap();
+
+        let model = |properties: &[(&'static str, &'static str)]| {
+            let properties: HashMap<&str, &str> = properties.iter().copied().collect();
+            state
+                .variant_for(&properties)
+                .and_then(|v| v.first())
+                .map(|v| (v.model.as_str(), v.y_rotation))
+        };
+
+        assert_eq!(
+            model(&[("facing", "north"), ("lit", "false")]),
+            Some(("block/furnace", 0))
+        );
+        assert_eq!(
+            model(&[("facing", "east"), ("lit", "false")]),
+            Some(("block/furnace", 90))
+        );
+        assert_eq!(
+            model(&[("facing", "north"), ("lit", "true")]),
+            Some(("block/furnace_on", 0))
+        );
+    }
+
+    #[test]
+    fn multipart_test() {
+        let state = BlockRenderState::from_str(
+            r#"{
+    "multipart": [
+        {   "apply": { "model": "block/oak_fence_post" }},
+        {   "when": { "north": "true" },
+            "apply": { "model": "block/oak_fence_side", "uvlock": true }
+        },
+        {   "when": { "east": true },
+            "apply": { "model": "block/oak_fence_side", "y": 90, "uvlock": true }
+        },
+        {   "when": { "OR": [
+                { "north": "low|tall", "west": "!none" },
+                { "AND": [ { "up": "true" }, { "south": "none" } ] }
+            ]},
+            "apply": { "model": "block/wall_post" }
+        }
+    ]
+}"#,
+        )
+        .unwrap();
+
+        let models = |properties: &[(&'static str, &'static str)]| {
+            let properties: HashMap<&str, &str> = properties.iter().copied().collect();
+            state
+                .multipart_variants_for(&properties)
+                .into_iter()
+                .filter_map(|v| v.first())
+                .map(|v| (v.model.as_str(), v.y_rotation))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(models(&[]), [("block/oak_fence_post", 0)]);
+        assert_eq!(
+            models(&[("north", "true"), ("east", "true")]),
+            [
+                ("block/oak_fence_post", 0),
+                ("block/oak_fence_side", 0),
+                ("block/oak_fence_side", 90)
+            ]
+        );
+        assert_eq!(
+            models(&[("north", "tall"), ("west", "low")]),
+            [("block/oak_fence_post", 0), ("block/wall_post", 0)]
+        );
+        assert_eq!(
+            models(&[("north", "tall"), ("west", "none")]),
+            [("block/oak_fence_post", 0)]
+        );
+        assert_eq!(
+            models(&[("up", "true"), ("south", "none")]),
+            [("block/oak_fence_post", 0), ("block/wall_post", 0)]
+        );
+    }
 }
@@ -1,17 +1,23 @@
+pub mod baked;
 pub mod block_state;
 pub mod model;
+pub mod random;
 
 pub mod texture;
+pub mod tint;
 
-use bevy_ecs::system::{ResMut, Resource};
+use bevy::{ecs::system::Resource, log::*};
 
-use std::{collections::HashMap, fs, io::BufReader, path::PathBuf};
-
-use log::*;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use self::{
     block_state::BlockRenderState,
     model::{BlockModel, Cube},
+    texture::{AtlasRect, TextureMeta},
 };
 
 pub struct BlockModelRef<'a> {
@@ -64,72 +70,103 @@ impl<'a> BlockModelRef<'a> {
     }
 }
 
-#[derive(Debug, Resource)]
-pub struct TextureIdMap(HashMap<String, usize>);
+#[derive(Debug, Default, Clone, Resource)]
+pub struct TextureIdMap {
+    ids: HashMap<String, usize>,
+    names: Vec<String>,
+
+    /// Where each texture was placed in the atlas, indexed by id.
+    rects: Vec<AtlasRect>,
+    atlas_size: (u32, u32),
+}
+
+impl TextureIdMap {
+    pub fn get_or_insert(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+
+        let id = self.names.len();
+        self.ids.insert(name.to_owned(), id);
+        self.names.push(name.to_owned());
+        id
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn set_atlas(&mut self, atlas_size: (u32, u32), rects: Vec<AtlasRect>) {
+        self.atlas_size = atlas_size;
+        self.rects = rects;
+    }
+
+    /// Maps a uv in texture space to a uv in atlas space.
+    pub fn atlas_uv(&self, id: usize, uv: glam::Vec2) -> glam::Vec2 {
+        let rect = self.rects[id];
+        let atlas_size = glam::Vec2::new(self.atlas_size.0 as f32, self.atlas_size.1 as f32);
+
+        (glam::Vec2::new(rect.x as f32, rect.y as f32)
+            + uv * glam::Vec2::new(rect.width as f32, rect.height as f32))
+            / atlas_size
+    }
+}
 
 #[derive(Debug, Resource)]
 pub struct LoadedAssets {
     block_models: HashMap<String, BlockModel>,
 
     block_states: HashMap<String, BlockRenderState>,
+
+    texture_path: PathBuf,
 }
 
 impl LoadedAssets {
-    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+    /// Loads the block models and blockstates of the resource pack at `path`. Files that can't be
+    /// read or parsed are logged and left out, only missing directories are an error.
+    pub fn from_path(path: impl Into<PathBuf>) -> Result<Self, AssetsError> {
         let path = path.into();
-        let mut block_states = HashMap::new();
-        let mut block_models = HashMap::new();
 
-        let block_model_path = path.join("models/block");
-        for path in walkdir::WalkDir::new(&block_model_path) {
-            let path = path.unwrap().path().to_owned();
+        Ok(Self {
+            block_models: load_json_files(&path.join("models/block"), BlockModel::from_str)?,
+            block_states: load_json_files(&path.join("blockstates"), BlockRenderState::from_str)?,
+            texture_path: path.join("textures"),
+        })
+    }
 
-            if !path.is_file() || !path.extension().map_or(false, |e| e == "json") {
-                continue;
+    pub fn load_texture(&self, name: &str) -> Option<image::RgbaImage> {
+        let path = self.texture_path.join(name).with_extension("png");
+        match image::open(&path) {
+            Ok(image) => Some(image.to_rgba8()),
+            Err(err) => {
+                error!("could not load texture {}: {}", path.display(), err);
+                None
             }
-
-            let mut name = "block/".to_string();
-
-            name.push_str(
-                path.strip_prefix(&block_model_path)
-                    .unwrap()
-                    .with_extension("")
-                    .to_str()
-                    .unwrap(),
-            );
-
-            let s = fs::read_to_string(path).unwrap();
-
-            block_models.insert(name, BlockModel::from_str(&s).unwrap());
         }
+    }
 
-        let block_state_path = path.join("blockstates");
-
-        for path in walkdir::WalkDir::new(&block_state_path) {
-            let path = path.unwrap().path().to_owned();
-
-            if !path.is_file() || !path.extension().map_or(false, |e| e == "json") {
-                continue;
-            }
+    /// Loads the `.png.mcmeta` next to a texture, if it has one.
+    pub fn load_texture_meta(&self, name: &str) -> Option<TextureMeta> {
+        let path = self.texture_path.join(name).with_extension("png.mcmeta");
+        let s = fs::read_to_string(&path).ok()?;
 
-            let mut name = "block/".to_string();
-            name.push_str(
-                path.strip_prefix(&block_state_path)
-                    .unwrap()
-                    .with_extension("")
-                    .to_str()
-                    .unwrap(),
-            );
-
-            let s = fs::read_to_string(path).unwrap();
+        serde_json::from_str(&s)
+            .map_err(|err| error!("could not parse {}: {}", path.display(), err))
+            .ok()
+    }
 
-            block_states.insert(name, BlockRenderState::from_str(&s).unwrap());
-        }
+    /// The names of every texture in `textures/block`, like `block/stone`.
+    pub fn block_texture_names(&self) -> Vec<String> {
+        let block_texture_path = self.texture_path.join("block");
 
-        Self {
-            block_models,
-            block_states,
-        }
+        files_with_extension(&block_texture_path, "png")
+            .into_iter()
+            .map(|path| block_name(&block_texture_path, &path))
+            .collect()
     }
 
     pub fn get_block_state(&self, name: &str) -> Option<&BlockRenderState> {
@@ -145,7 +182,9 @@ impl LoadedAssets {
                 .map(|parent| self.get_block_model(&parent));
             match parent {
                 Some(Some(parent)) => Some(BlockModelRef {
-                    ambient_occlusion: block_model.ambientocclusion,
+                    ambient_occlusion: block_model
+                        .ambientocclusion
+                        .unwrap_or(parent.ambient_occlusion),
                     parent: Some(Box::new(parent)),
                     textures: &block_model.textures,
                     elements: &block_model.elements,
@@ -153,7 +192,7 @@ impl LoadedAssets {
 
                 Some(None) => None,
                 _ => Some(BlockModelRef {
-                    ambient_occlusion: block_model.ambientocclusion,
+                    ambient_occlusion: block_model.ambientocclusion.unwrap_or(true),
                     parent: None,
                     textures: &block_model.textures,
                     elements: &block_model.elements,
@@ -164,3 +203,56 @@ impl LoadedAssets {
         }
     }
 }
+
+/// Why the assets of a resource pack couldn't be loaded.
+#[derive(Debug, thiserror::Error)]
+pub enum AssetsError {
+    #[error("could not find the asset directory {0}")]
+    MissingDirectory(PathBuf),
+}
+
+/// Parses every json file in `dir`, by its name like `block/stone`.
+fn load_json_files<T>(
+    dir: &Path,
+    parse: impl Fn(&str) -> serde_json::Result<T>,
+) -> Result<HashMap<String, T>, AssetsError> {
+    if !dir.is_dir() {
+        return Err(AssetsError::MissingDirectory(dir.to_owned()));
+    }
+
+    let mut files = HashMap::new();
+    for path in files_with_extension(dir, "json") {
+        let parsed = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|s| parse(&s).map_err(|err| err.to_string()));
+
+        match parsed {
+            Ok(parsed) => {
+                files.insert(block_name(dir, &path), parsed);
+            }
+            Err(err) => error!("could not load {}: {}", path.display(), err),
+        }
+    }
+    Ok(files)
+}
+
+/// Every file in `dir` and its subdirectories with `extension`. Entries that can't be read are
+/// logged and skipped.
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| {
+            entry
+                .map_err(|err| error!("could not read {}: {}", dir.display(), err))
+                .ok()
+        })
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| path.is_file() && path.extension().map_or(false, |e| e == extension))
+        .collect()
+}
+
+/// The name of the asset at `path` in `dir`, like `block/stone` for `dir/stone.json`.
+fn block_name(dir: &Path, path: &Path) -> String {
+    let name = path.strip_prefix(dir).unwrap_or(path).with_extension("");
+    format!("block/{}", name.to_string_lossy())
+}
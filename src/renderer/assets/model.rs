@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
+use azalea_core::direction::Direction;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct BlockModel {
-    #[serde(default = "r#true")]
-    pub ambientocclusion: bool,
+    /// Inherited from the parent when missing, and true if no model in the chain sets it.
+    pub ambientocclusion: Option<bool>,
     pub parent: Option<String>,
     #[serde(default)]
     pub textures: HashMap<String, String>,
@@ -34,6 +36,9 @@ pub struct Cube {
     pub to: glam::Vec3,
     pub rotation: Option<Rotation>,
     pub faces: Faces,
+    /// Whether the faces of this element get darker depending on their direction.
+    #[serde(default = "r#true")]
+    pub shade: bool,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -46,6 +51,19 @@ pub struct Faces {
     pub east: Option<Face>,
 }
 
+impl Faces {
+    pub fn get(&self, direction: Direction) -> Option<&Face> {
+        match direction {
+            Direction::Down => self.down.as_ref(),
+            Direction::Up => self.up.as_ref(),
+            Direction::North => self.north.as_ref(),
+            Direction::South => self.south.as_ref(),
+            Direction::West => self.west.as_ref(),
+            Direction::East => self.east.as_ref(),
+        }
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct Face {
     pub uv: Option<[f32; 4]>,
@@ -53,6 +71,8 @@ pub struct Face {
     pub cullface: Option<String>,
     #[serde(default)]
     pub rotation: i32,
+    /// Faces with a tint index are multiplied with the block's tint color.
+    pub tintindex: Option<i32>,
 }
 
 #[derive(serde::Deserialize, Debug)]
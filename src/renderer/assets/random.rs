@@ -0,0 +1,63 @@
+use azalea_core::position::BlockPos;
+
+const MULTIPLIER: i64 = 0x5DEECE66D;
+const INCREMENT: i64 = 0xB;
+const MASK: i64 = (1 << 48) - 1;
+
+/// The linear congruential generator used by `java.util.Random` and vanilla's
+/// `LegacyRandomSource`, needed to pick the same model variants as the vanilla client.
+#[derive(Debug, Clone)]
+pub struct LegacyRandom {
+    seed: i64,
+}
+
+impl LegacyRandom {
+    pub fn new(seed: i64) -> Self {
+        Self {
+            seed: (seed ^ MULTIPLIER) & MASK,
+        }
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = self.seed.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT) & MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+
+    pub fn next_long(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+}
+
+/// Vanilla's `Mth.getSeed`, the seed used for picking models for the block at `pos`.
+pub fn position_seed(pos: BlockPos) -> i64 {
+    let seed = (pos.x.wrapping_mul(3129871) as i64)
+        ^ (pos.z as i64).wrapping_mul(116129781)
+        ^ pos.y as i64;
+    let seed = seed
+        .wrapping_mul(seed)
+        .wrapping_mul(42317861)
+        .wrapping_add(seed.wrapping_mul(11));
+    seed >> 16
+}
+
+#[cfg(test)]
+mod tests {
+    use azalea_core::position::BlockPos;
+
+    use super::{position_seed, LegacyRandom};
+
+    #[test]
+    fn matches_java_random() {
+        assert_eq!(LegacyRandom::new(0).next_long(), -4962768465676381896);
+
+        let mut random = LegacyRandom::new(42);
+        assert_eq!(random.next_long(), -5025562857975149833);
+        assert_eq!(random.next_long(), -5843495416241995736);
+
+        assert_eq!(position_seed(BlockPos::new(-123, 64, 987)), -42294856385794);
+        assert_eq!(
+            position_seed(BlockPos::new(100000, -60, -5)),
+            44779730755713
+        );
+    }
+}
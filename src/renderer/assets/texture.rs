@@ -0,0 +1,629 @@
+use bevy::log::*;
+use image::RgbaImage;
+
+use super::{LoadedAssets, TextureIdMap};
+
+/// The sub-rectangle of the atlas a texture was placed in, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct TextureAtlas {
+    /// The atlas followed by its mip levels, each half the size of the one before.
+    pub levels: Vec<RgbaImage>,
+
+    /// The rect of every texture, indexed by texture id.
+    pub rects: Vec<AtlasRect>,
+
+    /// The rect every texture was aligned to, indexed by texture id. Textures are placed in the
+    /// top left of their slot.
+    pub slots: Vec<AtlasRect>,
+}
+
+/// Packs textures into a single image, using shelves of textures sorted by height.
+///
+/// With mip levels every texture is given a slot aligned to `1 << mip_levels` pixels, like
+/// vanilla, so no texel of any mip level mixes two textures and mips can't bleed across them.
+/// The space the alignment leaves around a texture is filled with copies of its edge pixels.
+#[derive(Default)]
+pub struct TextureAtlasBuilder {
+    textures: Vec<(usize, RgbaImage)>,
+    mip_levels: u32,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new(mip_levels: u32) -> Self {
+        Self {
+            textures: Vec::new(),
+            mip_levels,
+        }
+    }
+
+    pub fn add(&mut self, id: usize, image: RgbaImage) {
+        self.textures.push((id, image));
+    }
+
+    pub fn build(mut self) -> TextureAtlas {
+        let align = 1 << self.mip_levels;
+        let slot_size = |image: &RgbaImage| {
+            (
+                image.width().next_multiple_of(align),
+                image.height().next_multiple_of(align),
+            )
+        };
+
+        // Tallest first, so every shelf wastes as little height as possible.
+        self.textures.sort_by_key(|(_, image)| {
+            let (width, height) = slot_size(image);
+            std::cmp::Reverse((height, width))
+        });
+
+        let area: u64 = self
+            .textures
+            .iter()
+            .map(|(_, image)| {
+                let (width, height) = slot_size(image);
+                width as u64 * height as u64
+            })
+            .sum();
+        let max_width = self
+            .textures
+            .iter()
+            .map(|(_, image)| slot_size(image).0)
+            .max()
+            .unwrap_or(1);
+
+        let mut width = max_width.next_power_of_two().max(align);
+        while (width as u64) * (width as u64) < area {
+            width *= 2;
+        }
+
+        let mut rects = vec![AtlasRect::default(); self.textures.len()];
+        let mut slots = rects.clone();
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for (id, image) in &self.textures {
+            let (slot_width, slot_height) = slot_size(image);
+            if x + slot_width > width {
+                x = 0;
+                y += shelf_height;
+                shelf_height = 0;
+            }
+
+            if *id >= rects.len() {
+                rects.resize(id + 1, AtlasRect::default());
+                slots.resize(id + 1, AtlasRect::default());
+            }
+            slots[*id] = AtlasRect {
+                x,
+                y,
+                width: slot_width,
+                height: slot_height,
+            };
+            rects[*id] = AtlasRect {
+                x,
+                y,
+                width: image.width(),
+                height: image.height(),
+            };
+
+            x += slot_width;
+            shelf_height = shelf_height.max(slot_height);
+        }
+        let height = (y + shelf_height).max(align).next_power_of_two();
+
+        let mut levels: Vec<_> = (0..=self.mip_levels)
+            .map(|level| RgbaImage::new(width >> level, height >> level))
+            .collect();
+        for (id, image) in &self.textures {
+            let slot = slots[*id];
+            let chain = padded_mip_chain(image, slot, self.mip_levels);
+            for (level, (atlas, image)) in levels.iter_mut().zip(chain).enumerate() {
+                image::imageops::replace(
+                    atlas,
+                    &image,
+                    (slot.x >> level) as i64,
+                    (slot.y >> level) as i64,
+                );
+            }
+        }
+
+        TextureAtlas {
+            levels,
+            rects,
+            slots,
+        }
+    }
+}
+
+/// Pads `image` out to the size of its slot by repeating its right and bottom edge pixels, and
+/// generates `levels` mip levels from it.
+pub fn padded_mip_chain(image: &RgbaImage, slot: AtlasRect, levels: u32) -> Vec<RgbaImage> {
+    let padded = RgbaImage::from_fn(slot.width, slot.height, |x, y| {
+        *image.get_pixel(x.min(image.width() - 1), y.min(image.height() - 1))
+    });
+
+    // Cutout textures like leaves have fully transparent pixels, those should not darken or
+    // fade out the pixels around them.
+    let cutout = image.pixels().any(|pixel| pixel[3] == 0);
+
+    let mut chain = vec![padded];
+    for _ in 0..levels {
+        let next = downsample(chain.last().unwrap(), cutout);
+        chain.push(next);
+    }
+    chain
+}
+
+fn downsample(image: &RgbaImage, cutout: bool) -> RgbaImage {
+    RgbaImage::from_fn(image.width() / 2, image.height() / 2, |x, y| {
+        let pixels = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .map(|(dx, dy)| *image.get_pixel(x * 2 + dx, y * 2 + dy));
+        blend(pixels, cutout)
+    })
+}
+
+/// Averages four pixels, blending the colors in linear space so mips don't get darker.
+fn blend(pixels: [image::Rgba<u8>; 4], cutout: bool) -> image::Rgba<u8> {
+    let to_linear = |c: u8| (c as f32 / 255.0).powf(2.2);
+    let from_linear = |c: f32| (c.powf(1.0 / 2.2) * 255.0).round() as u8;
+
+    let visible = pixels
+        .iter()
+        .filter(|pixel| !cutout || pixel[3] != 0)
+        .collect::<Vec<_>>();
+    if visible.is_empty() {
+        return image::Rgba([0, 0, 0, 0]);
+    }
+
+    let mut color = [0; 4];
+    for channel in 0..3 {
+        let sum: f32 = visible.iter().map(|pixel| to_linear(pixel[channel])).sum();
+        color[channel] = from_linear(sum / visible.len() as f32);
+    }
+
+    let alpha = pixels.iter().map(|pixel| pixel[3] as u32).sum::<u32>() / 4;
+    // Like vanilla, mostly transparent texels of cutout textures are dropped entirely.
+    color[3] = if cutout && alpha < 96 { 0 } else { alpha as u8 };
+
+    image::Rgba(color)
+}
+
+/// The decoded images of the textures in a [`TextureIdMap`], indexed by texture id. Every texture
+/// is only decoded once, for the occlusion of the baked models and again for the atlas.
+#[derive(Default)]
+pub struct TextureImages {
+    images: Vec<Option<RgbaImage>>,
+    opaque: Vec<bool>,
+}
+
+impl TextureImages {
+    /// Decodes the textures added to `textures` since the last call.
+    pub fn load(&mut self, assets: &LoadedAssets, textures: &TextureIdMap) {
+        for id in self.images.len()..textures.len() {
+            let image = assets.load_texture(textures.name(id));
+            self.opaque.push(
+                image
+                    .as_ref()
+                    .is_some_and(|image| image.pixels().all(|pixel| pixel[3] == u8::MAX)),
+            );
+            self.images.push(image);
+        }
+    }
+
+    /// Whether every pixel of the texture is fully opaque. Textures that fail to load are not.
+    pub fn is_opaque(&self, id: usize) -> bool {
+        self.opaque[id]
+    }
+}
+
+/// Stitches every block texture and every texture referenced by the baked models into one atlas,
+/// and records where each texture ended up in `textures`. Animated textures are stitched with
+/// their first frame and returned so their regions can be updated every tick.
+pub fn stitch_atlas(
+    assets: &LoadedAssets,
+    textures: &mut TextureIdMap,
+    mut images: TextureImages,
+    mip_levels: u32,
+) -> (TextureAtlas, Vec<AnimatedTexture>) {
+    for name in assets.block_texture_names() {
+        textures.get_or_insert(&name);
+    }
+    images.load(assets, textures);
+
+    let mut builder = TextureAtlasBuilder::new(mip_levels);
+    let mut animated = Vec::new();
+    for (id, image) in images.images.into_iter().enumerate() {
+        let name = textures.name(id);
+        let Some(image) = image else {
+            warn!("using the missing texture for {}", name);
+            builder.add(id, missing_texture());
+            continue;
+        };
+
+        let Some(meta) = assets
+            .load_texture_meta(name)
+            .and_then(|meta| meta.animation)
+        else {
+            builder.add(id, image);
+            continue;
+        };
+
+        match meta.frame_size(&image) {
+            Some(frame_size) => {
+                let animation = Animation::new(image, meta, frame_size);
+                builder.add(id, animation.frame_image(animation.frames[0].0));
+                animated.push((id, animation));
+            }
+            None => {
+                warn!(
+                    "the animation frames of {} don't fit in its {}x{} image, it won't be animated",
+                    name,
+                    image.width(),
+                    image.height()
+                );
+                builder.add(id, image);
+            }
+        }
+    }
+
+    let atlas = builder.build();
+    info!(
+        "stitched {} textures, {} of them animated, into a {}x{} atlas with {} mip levels",
+        atlas.rects.len(),
+        animated.len(),
+        atlas.levels[0].width(),
+        atlas.levels[0].height(),
+        mip_levels
+    );
+
+    let animated = animated
+        .into_iter()
+        .map(|(id, animation)| AnimatedTexture {
+            slot: atlas.slots[id],
+            mip_levels,
+            animation,
+        })
+        .collect();
+
+    textures.set_atlas(atlas.levels[0].dimensions(), atlas.rects.clone());
+    (atlas, animated)
+}
+
+/// The contents of a `.png.mcmeta` file.
+#[derive(serde::Deserialize, Debug)]
+pub struct TextureMeta {
+    pub animation: Option<AnimationMeta>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct AnimationMeta {
+    /// The frames in the order they are shown, every frame of the texture in order if missing.
+    pub frames: Option<Vec<AnimationFrame>>,
+
+    /// How many ticks every frame without its own time is shown for.
+    #[serde(default = "default_frametime")]
+    pub frametime: u32,
+
+    /// Whether to blend between frames on the ticks in between them.
+    #[serde(default)]
+    pub interpolate: bool,
+
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl AnimationMeta {
+    /// The size of every frame in `image`, or `None` when not even one frame fits in it. Like
+    /// vanilla, frames are square unless the metadata says otherwise.
+    pub fn frame_size(&self, image: &RgbaImage) -> Option<(u32, u32)> {
+        let (frame_width, frame_height) = match (self.width, self.height) {
+            (Some(width), height) => (width, height.unwrap_or(image.height())),
+            (None, Some(height)) => (image.width(), height),
+            (None, None) => {
+                let size = image.width().min(image.height());
+                (size, size)
+            }
+        };
+
+        (frame_width > 0
+            && frame_height > 0
+            && frame_width <= image.width()
+            && frame_height <= image.height())
+        .then_some((frame_width, frame_height))
+    }
+}
+
+fn default_frametime() -> u32 {
+    1
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum AnimationFrame {
+    Index(u32),
+    Timed { index: u32, time: Option<u32> },
+}
+
+/// The frames of an animated texture and where in them the animation is.
+#[derive(Debug)]
+pub struct Animation {
+    image: RgbaImage,
+    frame_width: u32,
+    frame_height: u32,
+
+    /// The index and time of every frame in the order they are shown.
+    frames: Vec<(u32, u32)>,
+    interpolate: bool,
+
+    frame: usize,
+    sub_frame: u32,
+}
+
+impl Animation {
+    /// Splits `image` into frames of `frame_size`, which should come from
+    /// [`AnimationMeta::frame_size`].
+    pub fn new(image: RgbaImage, meta: AnimationMeta, frame_size: (u32, u32)) -> Self {
+        let (frame_width, frame_height) = frame_size;
+        let frame_count = (image.width() / frame_width) * (image.height() / frame_height);
+
+        let mut frames: Vec<_> = match meta.frames {
+            Some(frames) => frames
+                .into_iter()
+                .map(|frame| match frame {
+                    AnimationFrame::Index(index) => (index, meta.frametime),
+                    AnimationFrame::Timed { index, time } => {
+                        (index, time.unwrap_or(meta.frametime))
+                    }
+                })
+                .filter(|(index, _)| *index < frame_count)
+                .collect(),
+            None => (0..frame_count)
+                .map(|index| (index, meta.frametime))
+                .collect(),
+        };
+        if frames.is_empty() {
+            frames.push((0, meta.frametime));
+        }
+
+        Self {
+            image,
+            frame_width,
+            frame_height,
+            frames,
+            interpolate: meta.interpolate,
+            frame: 0,
+            sub_frame: 0,
+        }
+    }
+
+    pub fn frame_image(&self, index: u32) -> RgbaImage {
+        let columns = self.image.width() / self.frame_width;
+        image::imageops::crop_imm(
+            &self.image,
+            index % columns * self.frame_width,
+            index / columns * self.frame_height,
+            self.frame_width,
+            self.frame_height,
+        )
+        .to_image()
+    }
+
+    /// Advances the animation by a tick, returns the new image when it changed.
+    pub fn tick(&mut self) -> Option<RgbaImage> {
+        self.sub_frame += 1;
+
+        let (index, time) = self.frames[self.frame];
+        if self.sub_frame >= time {
+            self.frame = (self.frame + 1) % self.frames.len();
+            self.sub_frame = 0;
+
+            let next_index = self.frames[self.frame].0;
+            (next_index != index).then(|| self.frame_image(next_index))
+        } else if self.interpolate {
+            let next_index = self.frames[(self.frame + 1) % self.frames.len()].0;
+            Some(self.interpolated_frame(
+                index,
+                next_index,
+                1.0 - self.sub_frame as f64 / time as f64,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Mixes the colors of two frames like vanilla does, keeping the alpha of the first.
+    fn interpolated_frame(&self, index: u32, next_index: u32, ratio: f64) -> RgbaImage {
+        let mut image = self.frame_image(index);
+        let next = self.frame_image(next_index);
+
+        for (pixel, next) in image.pixels_mut().zip(next.pixels()) {
+            for channel in 0..3 {
+                pixel[channel] =
+                    (ratio * pixel[channel] as f64 + (1.0 - ratio) * next[channel] as f64) as u8;
+            }
+        }
+
+        image
+    }
+}
+
+/// An animated texture and the region of the atlas it is drawn to.
+#[derive(Debug)]
+pub struct AnimatedTexture {
+    pub slot: AtlasRect,
+    pub mip_levels: u32,
+    pub animation: Animation,
+}
+
+impl AnimatedTexture {
+    /// Advances the animation by a tick, returns the new contents of the texture's slot in every
+    /// mip level when they changed.
+    pub fn tick(&mut self) -> Option<Vec<RgbaImage>> {
+        let frame = self.animation.tick()?;
+        Some(padded_mip_chain(&frame, self.slot, self.mip_levels))
+    }
+}
+
+/// The magenta and black checkerboard vanilla uses for textures it could not load.
+fn missing_texture() -> RgbaImage {
+    RgbaImage::from_fn(16, 16, |x, y| {
+        if (x < 8) != (y < 8) {
+            image::Rgba([0, 0, 0, 255])
+        } else {
+            image::Rgba([248, 0, 248, 255])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+
+    use super::{blend, Animation, AnimationMeta, AtlasRect, TextureAtlasBuilder, TextureMeta};
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+        a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height && b.y < a.y + a.height
+    }
+
+    #[test]
+    fn pack_test() {
+        let sizes = [(16, 16), (16, 32), (32, 32), (16, 16), (64, 16), (16, 16)];
+
+        let mut builder = TextureAtlasBuilder::default();
+        for (id, (width, height)) in sizes.into_iter().enumerate() {
+            builder.add(
+                id,
+                RgbaImage::from_pixel(width, height, image::Rgba([id as u8, 0, 0, 255])),
+            );
+        }
+        let atlas = builder.build();
+        let image = &atlas.levels[0];
+
+        assert!(image.width().is_power_of_two());
+        assert!(image.height().is_power_of_two());
+
+        for (id, rect) in atlas.rects.iter().enumerate() {
+            assert_eq!((rect.width, rect.height), sizes[id]);
+            assert!(rect.x + rect.width <= image.width());
+            assert!(rect.y + rect.height <= image.height());
+            assert_eq!(image.get_pixel(rect.x, rect.y)[0], id as u8);
+
+            for other in &atlas.rects[id + 1..] {
+                assert!(!overlaps(rect, other), "{rect:?} overlaps {other:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn mip_test() {
+        let mut builder = TextureAtlasBuilder::new(2);
+        builder.add(
+            0,
+            RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255])),
+        );
+        builder.add(
+            1,
+            RgbaImage::from_pixel(6, 16, image::Rgba([0, 0, 255, 255])),
+        );
+        let atlas = builder.build();
+
+        assert_eq!(atlas.levels.len(), 3);
+        // Slots are only as big as the alignment needs, so textures that are already aligned
+        // take no extra space.
+        assert_eq!(
+            (atlas.levels[0].width(), atlas.levels[0].height()),
+            (32, 16)
+        );
+        for (id, slot) in atlas.slots.iter().enumerate() {
+            let rect = atlas.rects[id];
+            assert_eq!((slot.x % 4, slot.y % 4), (0, 0));
+            assert_eq!(
+                (slot.width, slot.height),
+                (
+                    rect.width.next_multiple_of(4),
+                    rect.height.next_multiple_of(4)
+                )
+            );
+
+            // Every texel of the slot is the texture's color in every level, padding included.
+            let color = atlas.levels[0].get_pixel(atlas.rects[id].x, atlas.rects[id].y);
+            for (level, image) in atlas.levels.iter().enumerate() {
+                for x in slot.x >> level..(slot.x + slot.width) >> level {
+                    for y in slot.y >> level..(slot.y + slot.height) >> level {
+                        assert_eq!(image.get_pixel(x, y), color);
+                    }
+                }
+            }
+        }
+
+        let clear = image::Rgba([0, 0, 0, 0]);
+        let green = image::Rgba([0, 200, 0, 255]);
+        // Transparent pixels of cutout textures don't darken the visible ones.
+        assert_eq!(
+            blend([green, clear, green, green], true),
+            image::Rgba([0, 200, 0, 191])
+        );
+        assert_eq!(blend([green, clear, clear, clear], true)[3], 0);
+        assert_eq!(blend([green, clear, clear, clear], false)[1], 107);
+    }
+
+    #[test]
+    fn animation_test() {
+        let meta: TextureMeta = serde_json::from_str(
+            r#"{
+    "animation": {
+        "interpolate": true,
+        "frametime": 2,
+        "frames": [ 1, { "index": 0, "time": 4 } ]
+    }
+}"#,
+        )
+        .unwrap();
+
+        // Two 2x2 frames stacked on top of each other, the first black and the second red.
+        let image = RgbaImage::from_fn(2, 4, |_, y| {
+            if y < 2 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([200, 0, 0, 255])
+            }
+        });
+        let meta = meta.animation.unwrap();
+        assert_eq!(meta.frame_size(&image), Some((2, 2)));
+        let mut animation = Animation::new(image, meta, (2, 2));
+        assert_eq!(animation.frames, [(1, 2), (0, 4)]);
+
+        let red = |image: Option<RgbaImage>| image.map(|image| image.get_pixel(0, 0)[0]);
+
+        // Halfway through frame 1, blending into frame 0.
+        assert_eq!(red(animation.tick()), Some(100));
+        assert_eq!(red(animation.tick()), Some(0));
+        assert_eq!(red(animation.tick()), Some(50));
+        assert_eq!(red(animation.tick()), Some(100));
+        assert_eq!(red(animation.tick()), Some(150));
+        assert_eq!(red(animation.tick()), Some(200));
+    }
+
+    #[test]
+    fn frame_size_test() {
+        let frame_size = |meta: &str, width, height| {
+            let meta: AnimationMeta = serde_json::from_str(meta).unwrap();
+            meta.frame_size(&RgbaImage::new(width, height))
+        };
+
+        assert_eq!(frame_size("{}", 16, 64), Some((16, 16)));
+        assert_eq!(frame_size(r#"{ "height": 8 }"#, 16, 64), Some((16, 8)));
+        assert_eq!(frame_size(r#"{ "width": 8 }"#, 16, 64), Some((8, 64)));
+
+        // Frames that are empty or bigger than the image leave the texture static.
+        assert_eq!(frame_size(r#"{ "width": 0 }"#, 16, 64), None);
+        assert_eq!(frame_size(r#"{ "height": 0 }"#, 16, 64), None);
+        assert_eq!(frame_size(r#"{ "width": 32 }"#, 16, 64), None);
+        assert_eq!(frame_size(r#"{ "height": 128 }"#, 16, 64), None);
+        assert_eq!(frame_size("{}", 0, 0), None);
+    }
+}
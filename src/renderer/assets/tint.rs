@@ -0,0 +1,148 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bevy::ecs::system::Resource;
+use image::RgbaImage;
+
+use super::LoadedAssets;
+
+/// The color the tinted faces of a block state are multiplied with, see vanilla's `BlockColors`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Tint {
+    #[default]
+    None,
+    Grass,
+    Foliage,
+    Water,
+    /// A color that doesn't depend on the biome, as `0xRRGGBB`.
+    Constant(u32),
+}
+
+impl Tint {
+    pub fn for_block(id: &str, properties: &HashMap<&str, &str>) -> Self {
+        match id {
+            "grass_block" | "short_grass" | "grass" | "tall_grass" | "fern" | "large_fern"
+            | "potted_fern" | "sugar_cane" | "pink_petals" => Tint::Grass,
+            "oak_leaves" | "jungle_leaves" | "acacia_leaves" | "dark_oak_leaves"
+            | "mangrove_leaves" | "vine" => Tint::Foliage,
+            "water" | "bubble_column" | "water_cauldron" => Tint::Water,
+
+            "spruce_leaves" => Tint::Constant(0x619961),
+            "birch_leaves" => Tint::Constant(0x80a755),
+            "lily_pad" => Tint::Constant(0x208030),
+            "attached_melon_stem" | "attached_pumpkin_stem" => Tint::Constant(0xe0c71c),
+            "melon_stem" | "pumpkin_stem" => {
+                let age: u32 = properties
+                    .get("age")
+                    .and_then(|age| age.parse().ok())
+                    .unwrap_or(0);
+                Tint::Constant((age * 32) << 16 | (255 - age * 8) << 8 | age * 4)
+            }
+            "redstone_wire" => {
+                let power: u32 = properties
+                    .get("power")
+                    .and_then(|power| power.parse().ok())
+                    .unwrap_or(0);
+                Tint::Constant(redstone_color(power))
+            }
+            _ => Tint::None,
+        }
+    }
+}
+
+/// The color of redstone wire with the given power level, like vanilla's `RedStoneWireBlock`.
+fn redstone_color(power: u32) -> u32 {
+    let f = power as f32 / 15.0;
+    let r = f * 0.6 + if f > 0.0 { 0.4 } else { 0.3 };
+    let g = (f * f * 0.7 - 0.5).clamp(0.0, 1.0);
+    let b = (f * f * 0.6 - 0.7).clamp(0.0, 1.0);
+
+    ((r * 255.0) as u32) << 16 | ((g * 255.0) as u32) << 8 | (b * 255.0) as u32
+}
+
+/// The grass and foliage colormaps, indexed by a biome's temperature and downfall.
+#[derive(Debug, Clone, Resource)]
+pub struct Colormaps {
+    grass: Arc<RgbaImage>,
+    foliage: Arc<RgbaImage>,
+}
+
+impl Colormaps {
+    pub fn new(grass: RgbaImage, foliage: RgbaImage) -> Self {
+        Self {
+            grass: Arc::new(grass),
+            foliage: Arc::new(foliage),
+        }
+    }
+
+    pub fn load(assets: &LoadedAssets) -> Self {
+        let load = |name| {
+            assets
+                .load_texture(name)
+                .unwrap_or_else(|| RgbaImage::new(0, 0))
+        };
+
+        Self::new(load("colormap/grass"), load("colormap/foliage"))
+    }
+
+    pub fn grass(&self, temperature: f32, downfall: f32) -> u32 {
+        // Vanilla's magenta, for colormaps that are missing or too small.
+        sample(&self.grass, temperature, downfall).unwrap_or(0xff00ff)
+    }
+
+    pub fn foliage(&self, temperature: f32, downfall: f32) -> u32 {
+        sample(&self.foliage, temperature, downfall).unwrap_or(0x48b518)
+    }
+}
+
+fn sample(colormap: &RgbaImage, temperature: f32, downfall: f32) -> Option<u32> {
+    let temperature = temperature.clamp(0.0, 1.0);
+    let downfall = downfall.clamp(0.0, 1.0) * temperature;
+
+    let x = ((1.0 - temperature) * 255.0) as u32;
+    let y = ((1.0 - downfall) * 255.0) as u32;
+
+    let pixel = colormap.get_pixel_checked(x, y)?;
+    Some((pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use image::RgbaImage;
+
+    use super::{Colormaps, Tint};
+
+    #[test]
+    fn tint_test() {
+        let tint = |id, properties: &[(&'static str, &'static str)]| {
+            Tint::for_block(id, &properties.iter().copied().collect::<HashMap<_, _>>())
+        };
+
+        assert_eq!(tint("grass_block", &[("snowy", "false")]), Tint::Grass);
+        assert_eq!(tint("stone", &[]), Tint::None);
+        assert_eq!(tint("birch_leaves", &[]), Tint::Constant(0x80a755));
+        // Unlike spruce and birch leaves, mangrove leaves follow the biome's foliage color.
+        assert_eq!(tint("mangrove_leaves", &[]), Tint::Foliage);
+        assert_eq!(
+            tint("redstone_wire", &[("power", "0")]),
+            Tint::Constant(0x4c0000)
+        );
+        assert_eq!(
+            tint("redstone_wire", &[("power", "15")]),
+            Tint::Constant(0xff3200)
+        );
+        assert_eq!(
+            tint("pumpkin_stem", &[("age", "7")]),
+            Tint::Constant(0xe0c71c)
+        );
+
+        let colormaps = Colormaps::new(
+            RgbaImage::from_fn(256, 256, |x, y| image::Rgba([x as u8, y as u8, 0, 255])),
+            RgbaImage::new(0, 0),
+        );
+        // Plains.
+        assert_eq!(colormaps.grass(0.8, 0.4), 0x32_ad_00);
+        assert_eq!(colormaps.foliage(0.8, 0.4), 0x48b518);
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use azalea_core::{
+    position::BlockPos, registry_holder::RegistryHolder, resource_location::ResourceLocation,
+};
+
+use super::{
+    assets::tint::{Colormaps, Tint},
+    mesher::ChunkLocal,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrassColorModifier {
+    None,
+    DarkForest,
+    Swamp,
+}
+
+/// The parts of a biome's registry entry that decide the colors of its blocks.
+#[derive(Debug, Clone)]
+pub struct Biome {
+    pub temperature: f32,
+    pub downfall: f32,
+    pub grass_color: Option<u32>,
+    pub foliage_color: Option<u32>,
+    pub water_color: u32,
+    pub grass_color_modifier: GrassColorModifier,
+}
+
+impl Default for Biome {
+    /// Plains, used for biomes the server didn't send.
+    fn default() -> Self {
+        Self {
+            temperature: 0.8,
+            downfall: 0.4,
+            grass_color: None,
+            foliage_color: None,
+            water_color: 0x3f76e4,
+            grass_color_modifier: GrassColorModifier::None,
+        }
+    }
+}
+
+/// Resolves the tint colors of blocks from the biomes of a world.
+#[derive(Debug, Clone)]
+pub struct BiomeColors {
+    /// Indexed by the biome ids used in chunk data.
+    biomes: Vec<Biome>,
+    colormaps: Colormaps,
+}
+
+impl BiomeColors {
+    pub fn new(registries: &RegistryHolder, colormaps: Colormaps) -> Self {
+        // The ids in chunk data are indices into the registry, which vanilla sorts by key. Keys are
+        // compared by path before namespace, like vanilla's `ResourceLocation::compareTo`.
+        let mut entries: Vec<_> = registries
+            .map
+            .get(&ResourceLocation::new("minecraft:worldgen/biome"))
+            .into_iter()
+            .flatten()
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| (&a.path, &a.namespace).cmp(&(&b.path, &b.namespace)));
+
+        let biomes = entries
+            .into_iter()
+            .map(|(_, nbt)| {
+                let default = Biome::default();
+                let effects = nbt.compound("effects");
+                let effect_color = |name| {
+                    effects
+                        .and_then(|effects| effects.int(name))
+                        .map(|c| c as u32)
+                };
+
+                Biome {
+                    temperature: nbt.float("temperature").unwrap_or(default.temperature),
+                    downfall: nbt.float("downfall").unwrap_or(default.downfall),
+                    grass_color: effect_color("grass_color"),
+                    foliage_color: effect_color("foliage_color"),
+                    water_color: effect_color("water_color").unwrap_or(default.water_color),
+                    grass_color_modifier: match effects
+                        .and_then(|effects| effects.string("grass_color_modifier"))
+                        .map(|modifier| modifier.to_str())
+                        .as_deref()
+                    {
+                        Some("dark_forest") => GrassColorModifier::DarkForest,
+                        Some("swamp") => GrassColorModifier::Swamp,
+                        _ => GrassColorModifier::None,
+                    },
+                }
+            })
+            .collect();
+
+        Self { biomes, colormaps }
+    }
+
+    pub fn biome(&self, id: u32) -> Biome {
+        self.biomes.get(id as usize).cloned().unwrap_or_default()
+    }
+
+    /// The color of `tint` in the biome with `id`, as `0xRRGGBB`.
+    pub fn tint_color(&self, tint: Tint, id: u32) -> u32 {
+        match tint {
+            Tint::None => 0xffffff,
+            Tint::Constant(color) => color,
+            Tint::Grass => self.grass_color(&self.biome(id)),
+            Tint::Foliage => {
+                let biome = self.biome(id);
+                biome
+                    .foliage_color
+                    .unwrap_or_else(|| self.colormaps.foliage(biome.temperature, biome.downfall))
+            }
+            Tint::Water => self.biome(id).water_color,
+        }
+    }
+
+    fn grass_color(&self, biome: &Biome) -> u32 {
+        let color = biome
+            .grass_color
+            .unwrap_or_else(|| self.colormaps.grass(biome.temperature, biome.downfall));
+
+        match biome.grass_color_modifier {
+            GrassColorModifier::None => color,
+            GrassColorModifier::DarkForest => ((color & 0xfefefe) + 0x28340a) >> 1,
+            // Vanilla switches between this and 0x4c763c with a noise function, only the more
+            // common color is used here.
+            GrassColorModifier::Swamp => 0x6a7039,
+        }
+    }
+}
+
+/// Averages biome tint colors over the blocks around a position, like vanilla's biome blend
+/// option. Positions are relative to the chunk of `local`.
+pub struct BiomeBlender<'a> {
+    local: &'a ChunkLocal,
+    colors: &'a BiomeColors,
+    radius: i32,
+
+    /// The summed colors and count of the blocks within the radius along x. Biomes are stored for
+    /// every 4 blocks vertically, so keys have their y rounded down to a multiple of 4.
+    rows: HashMap<(Tint, BlockPos), [u32; 4]>,
+}
+
+impl<'a> BiomeBlender<'a> {
+    pub fn new(local: &'a ChunkLocal, colors: &'a BiomeColors, radius: u32) -> Self {
+        Self {
+            local,
+            colors,
+            radius: radius.min(7) as i32,
+            rows: HashMap::new(),
+        }
+    }
+
+    /// The color of `tint` at `pos`, as `0xRRGGBB`.
+    pub fn color(&mut self, tint: Tint, pos: BlockPos) -> u32 {
+        if !matches!(tint, Tint::Grass | Tint::Foliage | Tint::Water) {
+            return self.colors.tint_color(tint, 0);
+        }
+        if self.radius == 0 {
+            return self.unblended(tint, pos).unwrap_or(0xffffff);
+        }
+
+        let pos = BlockPos::new(pos.x, pos.y & !3, pos.z);
+        let mut sum = [0; 4];
+        for dz in -self.radius..=self.radius {
+            let row = self.row(tint, BlockPos::new(pos.x, pos.y, pos.z + dz));
+            for (sum, row) in sum.iter_mut().zip(row) {
+                *sum += row;
+            }
+        }
+
+        let [r, g, b, count] = sum;
+        if count == 0 {
+            return 0xffffff;
+        }
+        (r / count) << 16 | (g / count) << 8 | b / count
+    }
+
+    fn row(&mut self, tint: Tint, pos: BlockPos) -> [u32; 4] {
+        if let Some(row) = self.rows.get(&(tint, pos)) {
+            return *row;
+        }
+
+        let mut row = [0; 4];
+        for dx in -self.radius..=self.radius {
+            // Blocks in chunks that aren't loaded are left out of the average.
+            if let Some(color) = self.unblended(tint, BlockPos::new(pos.x + dx, pos.y, pos.z)) {
+                row[0] += color >> 16 & 0xff;
+                row[1] += color >> 8 & 0xff;
+                row[2] += color & 0xff;
+                row[3] += 1;
+            }
+        }
+
+        self.rows.insert((tint, pos), row);
+        row
+    }
+
+    fn unblended(&self, tint: Tint, pos: BlockPos) -> Option<u32> {
+        let biome = self.local.get_biome(pos)?;
+        Some(self.colors.tint_color(tint, biome))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use azalea_core::position::BlockPos;
+    use image::RgbaImage;
+
+    use super::{Biome, BiomeBlender, BiomeColors};
+    use crate::renderer::{
+        assets::tint::{Colormaps, Tint},
+        light::ChunkLight,
+        mesher::ChunkLocal,
+    };
+
+    /// Colors for `biomes`, with a grass colormap that maps temperature and downfall to red and
+    /// green and no foliage colormap.
+    fn test_colors(biomes: Vec<Biome>) -> BiomeColors {
+        BiomeColors {
+            biomes,
+            colormaps: Colormaps::new(
+                RgbaImage::from_fn(256, 256, |x, y| image::Rgba([x as u8, y as u8, 0, 255])),
+                RgbaImage::new(0, 0),
+            ),
+        }
+    }
+
+    #[test]
+    fn tint_color_test() {
+        let colors = test_colors(vec![
+            Biome::default(),
+            // Mangrove swamp.
+            Biome {
+                foliage_color: Some(0x8db127),
+                ..Default::default()
+            },
+        ]);
+        let tint = |id| Tint::for_block(id, &HashMap::new());
+
+        assert_eq!(colors.tint_color(tint("mangrove_leaves"), 0), 0x48b518);
+        assert_eq!(colors.tint_color(tint("mangrove_leaves"), 1), 0x8db127);
+        assert_eq!(colors.tint_color(tint("spruce_leaves"), 1), 0x619961);
+        // Biomes the server didn't send are plains.
+        assert_eq!(colors.tint_color(tint("grass_block"), 7), 0x32ad00);
+    }
+
+    #[test]
+    fn blend_test() {
+        let colors = test_colors(vec![
+            Biome {
+                water_color: 0x000000,
+                ..Default::default()
+            },
+            Biome {
+                water_color: 0xf0f0f0,
+                ..Default::default()
+            },
+        ]);
+
+        // Biome 0 in the west half of the section at y 0, biome 1 in the east half. None of the
+        // neighbouring chunks are loaded.
+        let mut chunk = azalea_world::Chunk::default();
+        for x in 2..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    chunk.sections[4].biomes.set(x, y, z, 1);
+                }
+            }
+        }
+        let local = ChunkLocal {
+            chunk,
+            neighbers: Default::default(),
+            light: ChunkLight::default(),
+            neighbour_lights: Default::default(),
+            has_sky_light: true,
+            min_y: -64,
+        };
+
+        let color = |radius, x| {
+            BiomeBlender::new(&local, &colors, radius).color(Tint::Water, BlockPos::new(x, 4, 8))
+        };
+
+        // Without blending the colors change right at the edge.
+        assert_eq!(color(0, 7), 0x000000);
+        assert_eq!(color(0, 8), 0xf0f0f0);
+
+        // Two of the five columns on either side of the edge are in the other biome.
+        assert_eq!(color(2, 7), 0x606060);
+        assert_eq!(color(2, 8), 0x909090);
+        assert_eq!(color(2, 4), 0x000000);
+        assert_eq!(color(2, 11), 0xf0f0f0);
+
+        // Blocks in chunks that aren't loaded are left out instead of darkening the edges.
+        assert_eq!(color(2, 15), 0xf0f0f0);
+        // Three of the eleven loaded columns are in biome 0.
+        assert_eq!(color(7, 12), 0xaeaeae);
+    }
+}
@@ -0,0 +1,55 @@
+#import bevy_pbr::mesh_functions::{get_model_matrix, mesh_position_local_to_clip}
+
+@group(2) @binding(0) var<uniform> sky_brightness: f32;
+@group(2) @binding(1) var atlas_texture: texture_2d<f32>;
+@group(2) @binding(2) var atlas_sampler: sampler;
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    // The sky and block light, from 0 to 1.
+    @location(3) light: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) light: vec2<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = mesh_position_local_to_clip(
+        get_model_matrix(vertex.instance_index),
+        vec4<f32>(vertex.position, 1.0),
+    );
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.light = vertex.light;
+    return out;
+}
+
+// Vanilla's brightness curve for a light level, see `LightTexture::getBrightness`.
+fn brightness(level: f32) -> f32 {
+    return level / (4.0 - 3.0 * level);
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texture = textureSample(atlas_texture, atlas_sampler, in.uv);
+    if texture.a < 0.5 {
+        discard;
+    }
+
+    let sky = brightness(in.light.x) * sky_brightness;
+    let block = brightness(in.light.y);
+    // Like vanilla's lightmap, nothing is completely black.
+    let light = mix(max(sky, block), 0.75, 0.04);
+
+    // The light is applied in sRGB space like vanilla, the texture and vertex color are linear.
+    return vec4<f32>(texture.rgb * in.color.rgb * pow(light, 2.2), texture.a);
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Cursor,
+    sync::Arc,
+};
+
+use azalea_buf::{McBufReadable, McBufWritable};
+use azalea_client::chunks::ReceiveChunkEvent;
+use azalea_core::{
+    bitset::BitSet,
+    position::{ChunkBlockPos, ChunkPos},
+    resource_location::ResourceLocation,
+};
+use azalea_protocol::packets::game::clientbound_light_update_packet::ClientboundLightUpdatePacketData;
+use azalea_world::InstanceName;
+use bevy::ecs::{
+    event::EventReader,
+    system::{Query, Res, Resource},
+};
+use parking_lot::RwLock;
+
+/// The size of a section's light data, a nibble for every block.
+const LAYER_SIZE: usize = 2048;
+
+/// The light levels of a chunk, which the server sends separately from its blocks. Sections are
+/// indexed from the one below the bottom of the world, since light is sent for one section past
+/// each end of the world.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkLight {
+    sky: Vec<Option<Arc<[u8]>>>,
+    block: Vec<Option<Arc<[u8]>>>,
+}
+
+impl ChunkLight {
+    /// Applies the sections sent in a light update, the other sections keep their light.
+    pub fn update(&mut self, data: &ClientboundLightUpdatePacketData) {
+        update_layers(
+            &mut self.sky,
+            &data.sky_y_mask,
+            &data.empty_sky_y_mask,
+            &data.sky_updates,
+        );
+        update_layers(
+            &mut self.block,
+            &data.block_y_mask,
+            &data.empty_block_y_mask,
+            &data.block_updates,
+        );
+    }
+
+    /// The sky light at `pos`, from 0 to 15. Like vanilla, a section without sky light data is
+    /// lit like the bottom of the nearest section above it that has data, or fully if there is
+    /// none.
+    pub fn sky_light(&self, pos: &ChunkBlockPos, min_y: i32) -> u8 {
+        let Some(mut index) = section_index(pos.y, min_y) else {
+            return 0;
+        };
+
+        let mut y = pos.y.rem_euclid(16);
+        while let Some(layer) = self.sky.get(index) {
+            if let Some(layer) = layer {
+                return nibble(layer, pos.x, y, pos.z);
+            }
+            index += 1;
+            y = 0;
+        }
+        15
+    }
+
+    /// The block light at `pos`, from 0 to 15.
+    pub fn block_light(&self, pos: &ChunkBlockPos, min_y: i32) -> u8 {
+        section_index(pos.y, min_y)
+            .and_then(|index| self.block.get(index)?.as_ref())
+            .map_or(0, |layer| nibble(layer, pos.x, pos.y.rem_euclid(16), pos.z))
+    }
+}
+
+/// The indices of the sections whose light a light update changes.
+pub fn updated_sections(data: &ClientboundLightUpdatePacketData) -> BTreeSet<usize> {
+    [
+        &data.sky_y_mask,
+        &data.block_y_mask,
+        &data.empty_sky_y_mask,
+        &data.empty_block_y_mask,
+    ]
+    .into_iter()
+    .flat_map(set_bits)
+    .collect()
+}
+
+fn section_index(y: i32, min_y: i32) -> Option<usize> {
+    usize::try_from(((y - min_y) >> 4) + 1).ok()
+}
+
+fn nibble(layer: &[u8], x: u8, y: i32, z: u8) -> u8 {
+    let index = (y as usize) << 8 | (z as usize) << 4 | x as usize;
+    layer[index >> 1] >> ((index & 1) * 4) & 0xf
+}
+
+fn update_layers(
+    layers: &mut Vec<Option<Arc<[u8]>>>,
+    mask: &BitSet,
+    empty_mask: &BitSet,
+    updates: &[Vec<u8>],
+) {
+    let mut set = |index: usize, layer: Arc<[u8]>| {
+        if layers.len() <= index {
+            layers.resize(index + 1, None);
+        }
+        layers[index] = Some(layer);
+    };
+
+    for (index, update) in set_bits(mask).zip(updates) {
+        if update.len() == LAYER_SIZE {
+            set(index, update.as_slice().into());
+        }
+    }
+    for index in set_bits(empty_mask) {
+        set(index, Arc::new([0; LAYER_SIZE]));
+    }
+}
+
+/// The indices of the set bits in `bits`.
+fn set_bits(bits: &BitSet) -> impl Iterator<Item = usize> {
+    // `BitSet` doesn't expose how many words it has and panics when indexed past them, so the
+    // words are read back from its network form.
+    let mut buf = Vec::new();
+    bits.write_into(&mut buf)
+        .expect("writing to a vec should never fail");
+    let words = Vec::<u64>::read_from(&mut Cursor::new(&buf[..])).unwrap_or_default();
+
+    words.into_iter().enumerate().flat_map(|(i, word)| {
+        (0..64)
+            .filter(move |bit| word >> bit & 1 == 1)
+            .map(move |bit| i * 64 + bit)
+    })
+}
+
+/// The light of every loaded chunk by the world it's in and its position, shared with the meshing
+/// task.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct LightStorage(pub Arc<RwLock<HashMap<(ResourceLocation, ChunkPos), ChunkLight>>>);
+
+pub fn store_chunk_light_system(
+    mut events: EventReader<ReceiveChunkEvent>,
+    storage: Res<LightStorage>,
+
+    query: Query<&InstanceName>,
+) {
+    let mut lights = storage.0.write();
+    for event in events.read() {
+        let Ok(instance_name) = query.get(event.entity) else {
+            continue;
+        };
+
+        let mut light = ChunkLight::default();
+        light.update(&event.packet.light_data);
+        let pos = ChunkPos::new(event.packet.x, event.packet.z);
+        lights.insert(((**instance_name).clone(), pos), light);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use azalea_core::position::ChunkBlockPos;
+    use azalea_protocol::packets::game::clientbound_light_update_packet::ClientboundLightUpdatePacketData;
+
+    use super::{updated_sections, ChunkLight, LAYER_SIZE};
+
+    #[test]
+    fn light_test() {
+        let mut layer = [0; LAYER_SIZE];
+        // x = 1, y = 0, z = 0 is the high nibble of the first byte.
+        layer[0] = 0x7 << 4;
+        // x = 0, y = 15, z = 0.
+        layer[(15 << 8) >> 1] = 0x3;
+
+        let light = ChunkLight {
+            sky: vec![None, Some(Arc::new(layer)), None, None],
+            block: vec![None, Some(Arc::new(layer))],
+        };
+        let pos = |x, y, z| ChunkBlockPos::new(x, y, z);
+
+        assert_eq!(light.block_light(&pos(1, -64, 0), -64), 7);
+        assert_eq!(light.block_light(&pos(0, -49, 0), -64), 3);
+        assert_eq!(light.block_light(&pos(0, -48, 0), -64), 0);
+
+        // Sections without data take their sky light from the bottom of the section above.
+        assert_eq!(light.sky_light(&pos(1, -80, 0), -64), 7);
+        assert_eq!(light.sky_light(&pos(0, -65, 0), -64), 0);
+        // And are fully lit when there is none.
+        assert_eq!(light.sky_light(&pos(0, -48, 0), -64), 15);
+        assert_eq!(light.sky_light(&pos(0, -81, 0), -64), 0);
+    }
+
+    #[test]
+    fn update_test() {
+        let data = |block_y_mask: u64, empty_block_y_mask: u64, block_updates| {
+            ClientboundLightUpdatePacketData {
+                sky_y_mask: vec![].into(),
+                block_y_mask: vec![block_y_mask].into(),
+                empty_sky_y_mask: vec![].into(),
+                empty_block_y_mask: vec![empty_block_y_mask].into(),
+                sky_updates: vec![],
+                block_updates,
+            }
+        };
+        let pos = ChunkBlockPos::new(0, -64, 0);
+
+        let mut light = ChunkLight::default();
+        let update = data(0b110, 0, vec![vec![0; LAYER_SIZE], vec![0xff; LAYER_SIZE]]);
+        light.update(&update);
+        assert_eq!(
+            updated_sections(&update).into_iter().collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(light.block_light(&pos, -64), 0);
+        assert_eq!(light.block_light(&ChunkBlockPos::new(0, -48, 0), -64), 15);
+
+        // Sections that aren't in an update keep their light.
+        light.update(&data(0b10, 0, vec![vec![0x11; LAYER_SIZE]]));
+        assert_eq!(light.block_light(&pos, -64), 1);
+        assert_eq!(light.block_light(&ChunkBlockPos::new(0, -48, 0), -64), 15);
+
+        light.update(&data(0, 0b100, vec![]));
+        assert_eq!(light.block_light(&ChunkBlockPos::new(0, -48, 0), -64), 0);
+    }
+}
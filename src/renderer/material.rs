@@ -0,0 +1,168 @@
+use std::f32::consts::PI;
+
+use azalea_client::packet_handling::game::PacketEvent;
+use azalea_core::tick::GameTick;
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use bevy::{
+    app::{App, Plugin, Update},
+    asset::{load_internal_asset, Asset, Assets, Handle},
+    ecs::{
+        event::EventReader,
+        schedule::{
+            common_conditions::{resource_changed, resource_exists},
+            Condition, IntoSystemConfigs,
+        },
+        system::{Res, ResMut, Resource},
+    },
+    pbr::{AlphaMode, Material, MaterialPipeline, MaterialPipelineKey, MaterialPlugin},
+    reflect::TypePath,
+    render::{
+        mesh::{Mesh, MeshVertexAttribute, MeshVertexBufferLayout},
+        render_resource::{
+            AsBindGroup, RenderPipelineDescriptor, Shader, ShaderRef, SpecializedMeshPipelineError,
+            VertexFormat,
+        },
+        texture::Image,
+    },
+};
+
+use super::BlockMaterial;
+
+const CHUNK_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(6718236018562413990);
+
+/// The sky and block light of a vertex, each from 0 to 1.
+pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Light", 988540917, VertexFormat::Float32x2);
+
+/// The material chunk meshes are drawn with, lights the block atlas with the light of every
+/// vertex.
+#[derive(Debug, Clone, Asset, TypePath, AsBindGroup)]
+pub struct ChunkMaterial {
+    /// How bright sky light is at the current time of day, from 0.2 at night to 1.
+    #[uniform(0)]
+    pub sky_brightness: f32,
+    #[texture(1)]
+    #[sampler(2)]
+    pub atlas: Handle<Image>,
+}
+
+impl Material for ChunkMaterial {
+    fn vertex_shader() -> ShaderRef {
+        CHUNK_SHADER_HANDLE.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER_HANDLE.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Mask(0.5)
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(1),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(2),
+            ATTRIBUTE_LIGHT.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+pub struct ChunkMaterialPlugin;
+
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, CHUNK_SHADER_HANDLE, "chunk.wgsl", Shader::from_wgsl);
+
+        app.add_plugins(MaterialPlugin::<ChunkMaterial> {
+            prepass_enabled: false,
+            ..Default::default()
+        })
+        .init_resource::<DayTime>()
+        .add_systems(GameTick, advance_day_time_system)
+        .add_systems(
+            Update,
+            (
+                set_day_time_system,
+                update_sky_brightness_system
+                    .run_if(resource_exists::<BlockMaterial>.and_then(resource_changed::<DayTime>)),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The time of day in ticks, synced from the server and advanced every tick in between.
+#[derive(Debug, Default, Resource)]
+struct DayTime {
+    time: u64,
+    /// Whether the daylight cycle is running.
+    advancing: bool,
+}
+
+fn set_day_time_system(mut events: EventReader<PacketEvent>, mut day_time: ResMut<DayTime>) {
+    for event in events.read() {
+        if let ClientboundGamePacket::SetTime(packet) = &*event.packet {
+            // A negative time means the daylight cycle is stopped.
+            let time = packet.day_time as i64;
+            *day_time = DayTime {
+                time: time.unsigned_abs(),
+                advancing: time >= 0,
+            };
+        }
+    }
+}
+
+fn advance_day_time_system(mut day_time: ResMut<DayTime>) {
+    if day_time.advancing {
+        day_time.time += 1;
+    }
+}
+
+fn update_sky_brightness_system(
+    day_time: Res<DayTime>,
+    material: Res<BlockMaterial>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    if let Some(material) = materials.get_mut(material.0.id()) {
+        material.sky_brightness = sky_brightness(day_time.time);
+    }
+}
+
+/// How bright sky light is at `day_time`, like vanilla's `ClientLevel::getSkyDarken` without
+/// weather.
+fn sky_brightness(day_time: u64) -> f32 {
+    let time = time_of_day(day_time);
+    let brightness = ((time * PI * 2.0).cos() * 2.0 + 0.2).clamp(0.0, 1.0);
+    brightness * 0.8 + 0.2
+}
+
+/// How far the sun is through the day, 0 at noon, like vanilla's `DimensionType::timeOfDay`.
+fn time_of_day(day_time: u64) -> f32 {
+    let d = ((day_time % 24000) as f64 / 24000.0 - 0.25).rem_euclid(1.0);
+    let e = 0.5 - (d * std::f64::consts::PI).cos() / 2.0;
+    ((d * 2.0 + e) / 3.0) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sky_brightness;
+
+    #[test]
+    fn sky_brightness_test() {
+        // Noon and midnight.
+        assert_eq!(sky_brightness(6000), 1.0);
+        assert_eq!(sky_brightness(18000), 0.2);
+        assert_eq!(sky_brightness(30000), 1.0);
+        // The sky darkens through dusk.
+        assert!(sky_brightness(12000) < 1.0 && sky_brightness(12000) > sky_brightness(13000));
+    }
+}
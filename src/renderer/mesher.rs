@@ -1,13 +1,24 @@
-use std::{array, sync::Arc, time::Instant};
+use std::{
+    array,
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
 
-use azalea_client::{chunks::ReceiveChunkEvent, InstanceHolder};
+use azalea_client::{
+    chunks::ReceiveChunkEvent, disconnect::DisconnectEvent, packet_handling::game::PacketEvent,
+    InstanceHolder,
+};
 use azalea_core::{
     direction::Direction,
     position::{BlockPos, ChunkBlockPos, ChunkPos, ChunkSectionPos},
+    resource_location::ResourceLocation,
     tick::GameTick,
 };
-use azalea_physics::collision::BlockWithShape;
+use azalea_protocol::packets::game::ClientboundGamePacket;
+use azalea_world::InstanceName;
 use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
     prelude::*,
     render::{
         mesh::{Indices, PrimitiveTopology},
@@ -16,17 +27,52 @@ use bevy::{
     tasks::AsyncComputeTaskPool,
 };
 use bevy_flycam::FlyCam;
-use glam::IVec3;
 use parking_lot::RwLock;
 
+use super::{
+    ao::{flat_light, quad_ao, quad_indices, quad_light, AO_BRIGHTNESS},
+    assets::{
+        baked::{
+            direction_index, direction_normal, opposite_direction, BakedQuad, BlockStateModels,
+            FaceMask, DIRECTIONS,
+        },
+        tint::{Colormaps, Tint},
+        TextureIdMap,
+    },
+    biome::{BiomeBlender, BiomeColors},
+    light::{store_chunk_light_system, updated_sections, ChunkLight, LightStorage},
+    material::ATTRIBUTE_LIGHT,
+    queue::MeshQueue,
+    BiomeBlend, BlockMaterial, MaxMeshingJobs,
+};
+
 #[derive(Debug)]
 pub struct ChunkLocal {
     pub chunk: azalea_world::Chunk,
 
     pub neighbers: [Option<azalea_world::Chunk>; 8],
+
+    pub light: ChunkLight,
+
+    pub neighbour_lights: [Option<ChunkLight>; 8],
+
+    /// Whether the world has sky light, worlds without it have a sky light of 0 everywhere.
+    pub has_sky_light: bool,
+
+    /// The y of the bottom of the world.
+    pub min_y: i32,
 }
 
 impl ChunkLocal {
+    /// The neighbouring chunks that aren't loaded, as bits indexed like [`index_to_offset`].
+    pub fn missing_neighbours(&self) -> u8 {
+        self.neighbers
+            .iter()
+            .enumerate()
+            .filter(|(_, neighbour)| neighbour.is_none())
+            .fold(0, |missing, (i, _)| missing | 1 << i)
+    }
+
     //BlockPos is relative to the chunk
     pub fn get_block(&self, pos: BlockPos) -> Option<azalea_block::BlockState> {
         let chunk_pos = ChunkPos::from(pos);
@@ -36,11 +82,44 @@ impl ChunkLocal {
         if let Some(chunk_idx) = offset_to_index(chunk_pos) {
             self.neighbers[chunk_idx]
                 .as_ref()
-                .map(|c| c.get(&pos, -64))?
+                .map(|c| c.get(&pos, self.min_y))?
         } else {
-            self.chunk.get(&pos, -64)
+            self.chunk.get(&pos, self.min_y)
         }
     }
+
+    /// Returns the id of the biome at `pos`, biomes are stored for every 4x4x4 blocks.
+    pub fn get_biome(&self, pos: BlockPos) -> Option<u32> {
+        let chunk = match offset_to_index(ChunkPos::from(pos)) {
+            Some(chunk_idx) => self.neighbers[chunk_idx].as_ref()?,
+            None => &self.chunk,
+        };
+
+        let section = chunk
+            .sections
+            .get(usize::try_from((pos.y - self.min_y) >> 4).ok()?)?;
+        Some(section.biomes.get(
+            (pos.x.rem_euclid(16) >> 2) as usize,
+            (pos.y.rem_euclid(16) >> 2) as usize,
+            (pos.z.rem_euclid(16) >> 2) as usize,
+        ))
+    }
+
+    /// Returns the sky and block light at `pos`, or `None` if its chunk isn't loaded.
+    pub fn get_light(&self, pos: BlockPos) -> Option<(u8, u8)> {
+        let light = match offset_to_index(ChunkPos::from(pos)) {
+            Some(chunk_idx) => self.neighbour_lights[chunk_idx].as_ref()?,
+            None => &self.light,
+        };
+
+        let pos = ChunkBlockPos::from(pos);
+        let sky = if self.has_sky_light {
+            light.sky_light(&pos, self.min_y)
+        } else {
+            0
+        };
+        Some((sky, light.block_light(&pos, self.min_y)))
+    }
 }
 
 pub fn index_to_offset(index: usize) -> Option<ChunkPos> {
@@ -75,71 +154,771 @@ pub fn offset_to_index(offset: ChunkPos) -> Option<usize> {
 pub struct ChunkAdded {
     pub pos: ChunkPos,
 
+    /// The y of the sections to mesh, or `None` to mesh the whole chunk.
+    pub sections: Option<BTreeSet<i32>>,
+
     pub world: Arc<RwLock<azalea_world::Instance>>,
+
+    /// The name of the world, which keeps the meshes of different worlds apart.
+    pub instance_name: ResourceLocation,
+
+    /// Whether the world is lit like the Nether, where faces are shaded less by direction.
+    pub constant_ambient_light: bool,
+
+    /// Whether the world has sky light, like the dimension type's `has_skylight`.
+    pub has_sky_light: bool,
+
+    /// The y of the bottom of the world and its height in blocks, from the dimension type.
+    pub min_y: i32,
+    pub height: u32,
 }
 
-#[derive(Debug, Resource)]
-pub struct ChunkSender {
-    pub chunks_send: flume::Sender<ChunkAdded>,
+impl ChunkAdded {
+    /// Sections outside of the world are left out of `sections`.
+    pub fn new(
+        pos: ChunkPos,
+        sections: Option<BTreeSet<i32>>,
+        instance: &InstanceHolder,
+        instance_name: &InstanceName,
+        dimensions: &Dimensions,
+    ) -> Self {
+        let dimension = dimensions.get(instance_name);
+        let (min_y, height) = {
+            let world = instance.instance.read();
+            (world.chunks.min_y, world.chunks.height)
+        };
+
+        let mut update = Self {
+            pos,
+            sections: None,
+            world: instance.instance.clone(),
+            instance_name: (**instance_name).clone(),
+            constant_ambient_light: dimension.constant_ambient_light,
+            has_sky_light: dimension.has_sky_light,
+            min_y,
+            height,
+        };
+        let range = update.section_range();
+        update.sections =
+            sections.map(|sections| sections.into_iter().filter(|y| range.contains(y)).collect());
+        update
+    }
+
+    /// The y of every section in the world.
+    pub fn section_range(&self) -> Range<i32> {
+        self.min_y >> 4..(self.min_y + self.height as i32) >> 4
+    }
+}
+
+/// How the chunks of a world are lit, from its dimension type.
+#[derive(Debug, Clone, Copy)]
+pub struct DimensionLighting {
+    /// Whether the dimension has the Nether's effects, like vanilla's
+    /// `DimensionSpecialEffects::constantAmbientLight`.
+    pub constant_ambient_light: bool,
+    /// The dimension type's `has_skylight`.
+    pub has_sky_light: bool,
+}
+
+impl Default for DimensionLighting {
+    /// The Overworld, used for worlds whose dimension type the server didn't send.
+    fn default() -> Self {
+        Self {
+            constant_ambient_light: false,
+            has_sky_light: true,
+        }
+    }
+}
+
+/// The lighting of every world a player was sent to, by the name of the world.
+#[derive(Debug, Default, Resource)]
+pub struct Dimensions(HashMap<ResourceLocation, DimensionLighting>);
+
+impl Dimensions {
+    pub fn get(&self, instance_name: &ResourceLocation) -> DimensionLighting {
+        self.0.get(instance_name).copied().unwrap_or_default()
+    }
+}
+
+/// A newly meshed section.
+#[derive(Debug)]
+pub struct SectionMesh {
+    pub instance_name: ResourceLocation,
+    pub pos: ChunkSectionPos,
+    /// `None` if the section has nothing to draw.
+    pub mesh: Option<Mesh>,
+    /// The neighbouring chunks that weren't loaded when the section was meshed, see
+    /// [`ChunkLocal::missing_neighbours`].
+    pub missing_neighbours: u8,
+    /// Increases with every section handed to a meshing job, so a mesh that finishes after a
+    /// newer one of the same section can be told apart.
+    pub generation: u64,
 }
 
 #[derive(Debug, Resource)]
 pub struct MeshReciver {
-    pub mesh_recv: flume::Receiver<(Transform, Mesh)>,
+    pub mesh_recv: flume::Receiver<SectionMesh>,
+}
+
+/// The end of the channel handed to the meshing task once the block models are baked.
+#[derive(Debug, Resource)]
+struct MeshTaskChannels {
+    mesh_send: flume::Sender<SectionMesh>,
 }
 
+/// The entity drawing every meshed section, by the world it's in and its position.
+#[derive(Debug, Default, Resource)]
+pub struct SectionEntities {
+    entities: HashMap<(ResourceLocation, ChunkSectionPos), Entity>,
+
+    /// The neighbouring chunks that weren't loaded when each section was meshed, by the world and
+    /// chunk of the section and then its y. Sections that were meshed with all their neighbours
+    /// are left out.
+    missing_neighbours: HashMap<(ResourceLocation, ChunkPos), HashMap<i32, u8>>,
+
+    /// The generation of the newest mesh received for each section.
+    generations: HashMap<(ResourceLocation, ChunkSectionPos), u64>,
+}
+
+impl SectionEntities {
+    pub fn get(&self, instance_name: &ResourceLocation, pos: ChunkSectionPos) -> Option<Entity> {
+        self.entities.get(&(instance_name.clone(), pos)).copied()
+    }
+
+    /// Removes the sections of `chunks` in the world with `instance_name`, returns their entities.
+    fn remove_chunks(
+        &mut self,
+        instance_name: &ResourceLocation,
+        chunks: &HashSet<ChunkPos>,
+    ) -> Vec<Entity> {
+        let mut removed = Vec::new();
+        self.entities.retain(|(name, pos), entity| {
+            let keep = name != instance_name || !chunks.contains(&ChunkPos::new(pos.x, pos.z));
+            if !keep {
+                removed.push(*entity);
+            }
+            keep
+        });
+        self.missing_neighbours
+            .retain(|(name, pos), _| name != instance_name || !chunks.contains(pos));
+        self.generations.retain(|(name, pos), _| {
+            name != instance_name || !chunks.contains(&ChunkPos::new(pos.x, pos.z))
+        });
+        removed
+    }
+
+    /// Removes every section in the world with `instance_name`, returns their entities.
+    fn remove_instance(&mut self, instance_name: &ResourceLocation) -> Vec<Entity> {
+        let mut removed = Vec::new();
+        self.entities.retain(|(name, _), entity| {
+            if name == instance_name {
+                removed.push(*entity);
+            }
+            name != instance_name
+        });
+        self.missing_neighbours
+            .retain(|(name, _), _| name != instance_name);
+        self.generations
+            .retain(|(name, _), _| name != instance_name);
+        removed
+    }
+
+    /// Records `generation` as the newest mesh of the section, returns false if a newer one was
+    /// already received.
+    fn update_generation(
+        &mut self,
+        instance_name: &ResourceLocation,
+        pos: ChunkSectionPos,
+        generation: u64,
+    ) -> bool {
+        let newest = self
+            .generations
+            .entry((instance_name.clone(), pos))
+            .or_default();
+        if generation < *newest {
+            return false;
+        }
+        *newest = generation;
+        true
+    }
+
+    fn set_missing_neighbours(
+        &mut self,
+        instance_name: &ResourceLocation,
+        pos: ChunkSectionPos,
+        missing: u8,
+    ) {
+        let key = (instance_name.clone(), ChunkPos::new(pos.x, pos.z));
+        if missing != 0 {
+            self.missing_neighbours
+                .entry(key)
+                .or_default()
+                .insert(pos.y, missing);
+        } else if let Some(sections) = self.missing_neighbours.get_mut(&key) {
+            sections.remove(&pos.y);
+            if sections.is_empty() {
+                self.missing_neighbours.remove(&key);
+            }
+        }
+    }
+
+    /// Takes the sections around the chunk at `pos` that were meshed while it wasn't loaded.
+    fn take_meshed_without(
+        &mut self,
+        instance_name: &ResourceLocation,
+        pos: ChunkPos,
+    ) -> Vec<ChunkSectionPos> {
+        let mut taken = Vec::new();
+        for offset in (0..8).filter_map(index_to_offset) {
+            let neighbour = pos + offset;
+            let key = (instance_name.clone(), neighbour);
+            let Some(sections) = self.missing_neighbours.get_mut(&key) else {
+                continue;
+            };
+
+            // The chunk is in the opposite direction as seen from the neighbour.
+            let bit = 1
+                << offset_to_index(ChunkPos::new(-offset.x, -offset.z))
+                    .expect("offset should be a neighbour");
+            sections.retain(|&y, missing| {
+                if *missing & bit == 0 {
+                    return true;
+                }
+                taken.push(ChunkSectionPos::new(neighbour.x, y, neighbour.z));
+                *missing &= !bit;
+                *missing != 0
+            });
+            if sections.is_empty() {
+                self.missing_neighbours.remove(&key);
+            }
+        }
+        taken
+    }
+}
+
+/// The chunks that were received and not forgotten since, by the world they're in. Shared with
+/// the meshing task, which skips the chunks that were unloaded while they were queued.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct LoadedChunks(pub Arc<RwLock<HashSet<(ResourceLocation, ChunkPos)>>>);
+
+impl LoadedChunks {
+    pub fn contains(&self, instance_name: &ResourceLocation, pos: ChunkPos) -> bool {
+        self.0.read().contains(&(instance_name.clone(), pos))
+    }
+}
+
+/// The biome colors of every world, built from its biome registry when its first chunk is meshed.
+/// Shared with the meshing task.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct WorldBiomeColors(pub Arc<RwLock<HashMap<ResourceLocation, Arc<BiomeColors>>>>);
+
+/// How many sections the mesh queue holds, has handed out and has merged. They are logged with
+/// the `log_mesh_queue` setting of the `RenderPlugin`.
+pub const QUEUED_SECTIONS: DiagnosticPath = DiagnosticPath::const_new("mesh_queue/queued");
+pub const MESHED_SECTIONS: DiagnosticPath = DiagnosticPath::const_new("mesh_queue/meshed");
+pub const MERGED_SECTIONS: DiagnosticPath = DiagnosticPath::const_new("mesh_queue/merged");
+
 pub struct ChunkMeshPlugin;
 
 impl Plugin for ChunkMeshPlugin {
     fn build(&self, app: &mut App) {
-        let (chunks_send, chunks_recv) = flume::unbounded();
         let (mesh_send, mesh_recv) = flume::unbounded();
-        app.add_systems(GameTick, send_chunks_system)
-            .add_systems(Update, (insert_mesh_system, test_system))
+        app.init_resource::<LightStorage>()
+            .init_resource::<Dimensions>()
+            .init_resource::<WorldBiomeColors>()
+            .init_resource::<MeshQueue>()
+            .init_resource::<SectionEntities>()
+            .init_resource::<LoadedChunks>()
+            .add_systems(
+                GameTick,
+                (
+                    dimensions_system,
+                    reset_worlds_system,
+                    forget_chunks_system,
+                    store_chunk_light_system,
+                    send_chunks_system,
+                    relight_system,
+                    block_update_system,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    insert_mesh_system.run_if(resource_exists::<BlockMaterial>),
+                    test_system,
+                    start_meshing_system.run_if(resource_added::<BlockStateModels>),
+                    queue_center_system,
+                    queue_diagnostics_system,
+                ),
+            )
             .insert_resource(MeshReciver { mesh_recv })
-            .insert_resource(ChunkSender { chunks_send });
+            .insert_resource(MeshTaskChannels { mesh_send })
+            .register_diagnostic(Diagnostic::new(QUEUED_SECTIONS))
+            .register_diagnostic(Diagnostic::new(MESHED_SECTIONS))
+            .register_diagnostic(Diagnostic::new(MERGED_SECTIONS));
+    }
+}
+
+fn start_meshing_system(
+    channels: Res<MeshTaskChannels>,
+    queue: Res<MeshQueue>,
+    models: Res<BlockStateModels>,
+    textures: Res<TextureIdMap>,
+    colormaps: Res<Colormaps>,
+    biome_blend: Res<BiomeBlend>,
+    max_jobs: Res<MaxMeshingJobs>,
+    lights: Res<LightStorage>,
+    loaded: Res<LoadedChunks>,
+    biome_colors: Res<WorldBiomeColors>,
+) {
+    let context = MeshContext {
+        mesh_send: channels.mesh_send.clone(),
+        models: models.clone(),
+        textures: textures.clone(),
+        biome_blend: biome_blend.0,
+        loaded: loaded.clone(),
+    };
+
+    let thread_pool = AsyncComputeTaskPool::get();
+    thread_pool
+        .spawn(create_meshes_task(
+            queue.clone(),
+            Arc::new(context),
+            colormaps.clone(),
+            lights.clone(),
+            biome_colors.clone(),
+            max_jobs.0.max(1),
+        ))
+        .detach();
+}
+
+/// Prioritises the sections nearest to the active camera.
+fn queue_center_system(queue: Res<MeshQueue>, cameras: Query<(&Camera, &GlobalTransform)>) {
+    let Some((_, transform)) = cameras.iter().find(|(camera, _)| camera.is_active) else {
+        return;
+    };
+
+    let pos = (transform.translation() / 16.0).floor();
+    queue.set_center(ChunkSectionPos::new(
+        pos.x as i32,
+        pos.y as i32,
+        pos.z as i32,
+    ));
+}
+
+fn queue_diagnostics_system(queue: Res<MeshQueue>, mut diagnostics: Diagnostics) {
+    let stats = queue.stats();
+    diagnostics.add_measurement(&QUEUED_SECTIONS, || stats.queued as f64);
+    diagnostics.add_measurement(&MESHED_SECTIONS, || stats.taken as f64);
+    diagnostics.add_measurement(&MERGED_SECTIONS, || stats.merged as f64);
+}
+
+/// Reads the dimension type of the world a player joins or respawns in.
+fn dimensions_system(
+    mut events: EventReader<PacketEvent>,
+    mut dimensions: ResMut<Dimensions>,
+    biome_colors: Res<WorldBiomeColors>,
 
-        let thread_pool = AsyncComputeTaskPool::get();
-        thread_pool
-            .spawn(create_meshes_task(chunks_recv, mesh_send))
-            .detach();
+    query: Query<&InstanceHolder>,
+) {
+    for event in events.read() {
+        let common = match &*event.packet {
+            ClientboundGamePacket::Login(packet) => &packet.common,
+            ClientboundGamePacket::Respawn(packet) => &packet.common,
+            _ => continue,
+        };
+        let Ok(local_player) = query.get(event.entity) else {
+            continue;
+        };
+        // The registries are sent again before the player is sent to a world.
+        biome_colors.0.write().remove(&common.dimension);
+
+        let world = local_player.instance.read();
+        let Some(dimension_type) = world
+            .registries
+            .map
+            .get(&ResourceLocation::new("minecraft:dimension_type"))
+            .and_then(|types| types.get(&common.dimension_type))
+        else {
+            continue;
+        };
+        let effects = dimension_type
+            .string("effects")
+            .map(|effects| effects.to_str());
+        dimensions.0.insert(
+            common.dimension.clone(),
+            DimensionLighting {
+                constant_ambient_light: effects.as_deref() == Some("minecraft:the_nether"),
+                has_sky_light: dimension_type
+                    .byte("has_skylight")
+                    .map_or(true, |has_sky_light| has_sky_light != 0),
+            },
+        );
     }
 }
 
 fn send_chunks_system(
     mut events: EventReader<ReceiveChunkEvent>,
-    sender: Res<ChunkSender>,
+    queue: Res<MeshQueue>,
+    dimensions: Res<Dimensions>,
+    mut entities: ResMut<SectionEntities>,
+    loaded: Res<LoadedChunks>,
 
-    query: Query<&InstanceHolder>,
+    query: Query<(&InstanceHolder, &InstanceName)>,
 ) {
     for event in events.read() {
         let pos = ChunkPos::new(event.packet.x, event.packet.z);
 
-        let local_player = query.get(event.entity).unwrap();
+        let (local_player, instance_name) = query.get(event.entity).unwrap();
+        loaded.0.write().insert(((**instance_name).clone(), pos));
+
+        queue.push(ChunkAdded::new(
+            pos,
+            None,
+            local_player,
+            instance_name,
+            &dimensions,
+        ));
+
+        // The sections around the chunk that were meshed without it are meshed again, so their
+        // faces along it get culled.
+        let mut dirty = DirtySections::default();
+        for section in entities.take_meshed_without(instance_name, pos) {
+            dirty.insert(section);
+        }
+        dirty.send(&queue, local_player, instance_name, &dimensions);
+    }
+}
+
+/// Despawns the meshes of the chunks the server unloaded, and drops their queued sections.
+fn forget_chunks_system(
+    mut commands: Commands,
+    mut events: EventReader<PacketEvent>,
+    queue: Res<MeshQueue>,
+    mut entities: ResMut<SectionEntities>,
+    loaded: Res<LoadedChunks>,
+    lights: Res<LightStorage>,
+
+    query: Query<&InstanceName>,
+) {
+    let mut forgotten: HashMap<ResourceLocation, HashSet<ChunkPos>> = HashMap::new();
+    for event in events.read() {
+        let ClientboundGamePacket::ForgetLevelChunk(packet) = &*event.packet else {
+            continue;
+        };
+        let Ok(instance_name) = query.get(event.entity) else {
+            continue;
+        };
+        forgotten
+            .entry((**instance_name).clone())
+            .or_default()
+            .insert(packet.pos);
+    }
+
+    for (instance_name, chunks) in forgotten {
+        {
+            let mut loaded = loaded.0.write();
+            let mut lights = lights.0.write();
+            for pos in &chunks {
+                loaded.remove(&(instance_name.clone(), *pos));
+                lights.remove(&(instance_name.clone(), *pos));
+            }
+        }
+
+        queue.remove_chunks(&instance_name, &chunks);
+        for entity in entities.remove_chunks(&instance_name, &chunks) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Despawns every mesh of a world when the player leaves it, by changing dimension, logging in
+/// again or disconnecting.
+fn reset_worlds_system(
+    mut commands: Commands,
+    mut disconnects: EventReader<DisconnectEvent>,
+    mut entities: ResMut<SectionEntities>,
+    loaded: Res<LoadedChunks>,
+    lights: Res<LightStorage>,
+    biome_colors: Res<WorldBiomeColors>,
+    mut instances: Local<HashMap<Entity, ResourceLocation>>,
+
+    query: Query<(Entity, &InstanceName), Changed<InstanceName>>,
+) {
+    // The world a player was in before its name changed, or before it was inserted again.
+    let mut left: Vec<ResourceLocation> = query
+        .iter()
+        .filter_map(|(entity, instance_name)| instances.insert(entity, (**instance_name).clone()))
+        .collect();
+    left.extend(
+        disconnects
+            .read()
+            .filter_map(|event| instances.remove(&event.entity)),
+    );
+
+    for instance_name in left {
+        loaded.0.write().retain(|(name, _)| *name != instance_name);
+        biome_colors.0.write().remove(&instance_name);
+        lights
+            .0
+            .write()
+            .retain(|(name, _), _| *name != instance_name);
+
+        for entity in entities.remove_instance(&instance_name) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Applies light updates and meshes the sections whose light changed again.
+fn relight_system(
+    mut events: EventReader<PacketEvent>,
+    queue: Res<MeshQueue>,
+    dimensions: Res<Dimensions>,
+    lights: Res<LightStorage>,
+
+    query: Query<(&InstanceHolder, &InstanceName)>,
+) {
+    for event in events.read() {
+        let ClientboundGamePacket::LightUpdate(packet) = &*event.packet else {
+            continue;
+        };
+        let Ok((local_player, instance_name)) = query.get(event.entity) else {
+            continue;
+        };
+
+        // Updates for chunks that were never sent or already forgotten are dropped, their light
+        // comes with the chunk when it is sent again.
+        let pos = ChunkPos::new(packet.x, packet.z);
+        {
+            let mut lights = lights.0.write();
+            let Some(light) = lights.get_mut(&((**instance_name).clone(), pos)) else {
+                continue;
+            };
+            light.update(&packet.light_data);
+        }
 
-        sender
-            .chunks_send
-            .send(ChunkAdded {
+        // Faces are lit by the blocks next to them, so the sections around a section whose light
+        // changed are meshed again too. Light sections start one section below the world.
+        let min_section = local_player.instance.read().chunks.min_y >> 4;
+        let mut dirty = DirtySections::default();
+        for index in updated_sections(&packet.light_data) {
+            let y = index as i32 - 1 + min_section;
+            dirty.insert_with_neighbours(ChunkSectionPos::new(pos.x, y, pos.z));
+        }
+        dirty.send(&queue, local_player, instance_name, &dimensions);
+    }
+}
+
+/// Meshes the sections of blocks the server changed again.
+fn block_update_system(
+    mut events: EventReader<PacketEvent>,
+    queue: Res<MeshQueue>,
+    dimensions: Res<Dimensions>,
+
+    query: Query<(&InstanceHolder, &InstanceName)>,
+) {
+    for event in events.read() {
+        let mut dirty = DirtySections::default();
+        match &*event.packet {
+            ClientboundGamePacket::BlockUpdate(packet) => dirty.insert_block(packet.pos),
+            ClientboundGamePacket::SectionBlocksUpdate(packet) => {
+                let origin = BlockPos::new(
+                    packet.section_pos.x * 16,
+                    packet.section_pos.y * 16,
+                    packet.section_pos.z * 16,
+                );
+                for state in &packet.states {
+                    dirty.insert_block(
+                        origin
+                            + BlockPos::new(
+                                state.pos.x as i32,
+                                state.pos.y as i32,
+                                state.pos.z as i32,
+                            ),
+                    );
+                }
+            }
+            _ => continue,
+        }
+
+        let Ok((local_player, instance_name)) = query.get(event.entity) else {
+            continue;
+        };
+        dirty.send(&queue, local_player, instance_name, &dimensions);
+    }
+}
+
+/// Sections to mesh again, grouped by chunk.
+#[derive(Debug, Default)]
+struct DirtySections(HashMap<ChunkPos, BTreeSet<i32>>);
+
+impl DirtySections {
+    fn insert(&mut self, pos: ChunkSectionPos) {
+        self.0
+            .entry(ChunkPos::new(pos.x, pos.z))
+            .or_default()
+            .insert(pos.y);
+    }
+
+    /// Marks every section from `min` to `max`.
+    fn insert_range(&mut self, min: ChunkSectionPos, max: ChunkSectionPos) {
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                self.0
+                    .entry(ChunkPos::new(x, z))
+                    .or_default()
+                    .extend(min.y..=max.y);
+            }
+        }
+    }
+
+    /// Marks `pos` and the sections around it.
+    fn insert_with_neighbours(&mut self, pos: ChunkSectionPos) {
+        self.insert_range(
+            ChunkSectionPos::new(pos.x - 1, pos.y - 1, pos.z - 1),
+            ChunkSectionPos::new(pos.x + 1, pos.y + 1, pos.z + 1),
+        );
+    }
+
+    /// Marks the sections a change to the block at `pos` shows up in. Like vanilla's
+    /// `LevelRenderer::setBlockDirty`, that's every section touching the blocks around it, which
+    /// cull, shade and light the faces next to it.
+    fn insert_block(&mut self, pos: BlockPos) {
+        self.insert_range(
+            ChunkSectionPos::from(pos + BlockPos::new(-1, -1, -1)),
+            ChunkSectionPos::from(pos + BlockPos::new(1, 1, 1)),
+        );
+    }
+
+    /// Queues the sections of the loaded chunks to be meshed.
+    fn send(
+        self,
+        queue: &MeshQueue,
+        local_player: &InstanceHolder,
+        instance_name: &InstanceName,
+        dimensions: &Dimensions,
+    ) {
+        let loaded: Vec<_> = {
+            let world = local_player.instance.read();
+            self.0
+                .into_iter()
+                .filter(|(pos, _)| world.chunks.get(pos).is_some())
+                .collect()
+        };
+
+        for (pos, sections) in loaded {
+            queue.push(ChunkAdded::new(
                 pos,
-                world: local_player.instance.clone(),
-            })
-            .unwrap();
+                Some(sections),
+                local_player,
+                instance_name,
+                dimensions,
+            ));
+        }
     }
 }
 
+/// Spawns the entities of newly meshed sections, or swaps the mesh of the section's entity if it
+/// already has one.
 fn insert_mesh_system(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut entities: ResMut<SectionEntities>,
+    material: Res<BlockMaterial>,
     recv_meshes: Res<MeshReciver>,
+    queue: Res<MeshQueue>,
+    dimensions: Res<Dimensions>,
+    loaded: Res<LoadedChunks>,
+
+    query: Query<(&InstanceHolder, &InstanceName)>,
 ) {
-    for (transform, mesh) in recv_meshes.mesh_recv.try_iter() {
-        commands.spawn(MaterialMeshBundle {
-            mesh: meshes.add(mesh),
-            material: materials.add(StandardMaterial::from(Color::WHITE)),
-            transform,
-            ..Default::default()
-        });
+    let mut dirty: HashMap<ResourceLocation, DirtySections> = HashMap::new();
+
+    for section in recv_meshes.mesh_recv.try_iter() {
+        // Meshes of chunks that were unloaded while they were being meshed are dropped.
+        if !loaded.contains(
+            &section.instance_name,
+            ChunkPos::new(section.pos.x, section.pos.z),
+        ) {
+            continue;
+        }
+        // Jobs can finish in any order, a mesh older than the section's current one is dropped.
+        if !entities.update_generation(&section.instance_name, section.pos, section.generation) {
+            continue;
+        }
+
+        let missing = if section.mesh.is_some() {
+            section.missing_neighbours
+        } else {
+            0
+        };
+        entities.set_missing_neighbours(&section.instance_name, section.pos, missing);
+
+        // A neighbour that was loaded while the section was being meshed won't see it in
+        // `send_chunks_system`, so it's meshed again here.
+        if missing != 0 {
+            if let Some((local_player, _)) = query
+                .iter()
+                .find(|(_, instance_name)| ***instance_name == section.instance_name)
+            {
+                let world = local_player.instance.read();
+                let loaded = (0..8).any(|i| {
+                    missing & 1 << i != 0
+                        && index_to_offset(i).is_some_and(|offset| {
+                            world
+                                .chunks
+                                .get(&(ChunkPos::new(section.pos.x, section.pos.z) + offset))
+                                .is_some()
+                        })
+                });
+                if loaded {
+                    dirty
+                        .entry(section.instance_name.clone())
+                        .or_default()
+                        .insert(section.pos);
+                }
+            }
+        }
+
+        let key = (section.instance_name, section.pos);
+
+        let Some(mesh) = section.mesh else {
+            if let Some(entity) = entities.entities.remove(&key) {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        let mesh = meshes.add(mesh);
+        if let Some(&entity) = entities.entities.get(&key) {
+            commands.entity(entity).insert(mesh);
+            continue;
+        }
+
+        let pos = section.pos;
+        let entity = commands
+            .spawn(MaterialMeshBundle {
+                mesh,
+                material: material.0.clone(),
+                transform: Transform::from_xyz(
+                    (pos.x * 16) as f32,
+                    (pos.y * 16) as f32,
+                    (pos.z * 16) as f32,
+                ),
+                ..Default::default()
+            })
+            .id();
+        entities.entities.insert(key, entity);
+    }
+
+    for (instance_name, dirty) in dirty {
+        if let Some((local_player, instance_name)) =
+            query.iter().find(|(_, name)| ***name == instance_name)
+        {
+            dirty.send(&queue, local_player, instance_name, &dimensions);
+        }
     }
 }
 
@@ -148,14 +927,47 @@ fn test_system(cameras: Query<(&FlyCam, &mut Transform)>) {
         println!("{:?}", transform);
     }
 }
+
+/// What every meshing job needs, shared between them.
+struct MeshContext {
+    mesh_send: flume::Sender<SectionMesh>,
+    models: BlockStateModels,
+    textures: TextureIdMap,
+    biome_blend: u32,
+    loaded: LoadedChunks,
+}
+
+/// A slot of the meshing jobs that may run at once, freed when the job holding it is dropped.
+struct JobSlot(flume::Receiver<()>);
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        let _ = self.0.try_recv();
+    }
+}
+
+/// Takes chunks from the queue and meshes each of their sections in its own job, waiting for a
+/// job to finish while `max_jobs` are running.
 async fn create_meshes_task(
-    chunks_recv: flume::Receiver<ChunkAdded>,
-    mesh_send: flume::Sender<(Transform, Mesh)>,
+    queue: MeshQueue,
+    context: Arc<MeshContext>,
+    colormaps: Colormaps,
+    lights: LightStorage,
+    world_biome_colors: WorldBiomeColors,
+    max_jobs: usize,
 ) {
-    while let Ok(update) = chunks_recv.recv_async().await {
-        let time = Instant::now();
+    let thread_pool = AsyncComputeTaskPool::get();
+    // Every running job holds a message in the channel, so sending blocks while it is full.
+    let (slots_send, slots_recv) = flume::bounded(max_jobs);
+    let mut generation = 0;
+
+    loop {
+        let update = queue.pop().await;
+        if !context.loaded.contains(&update.instance_name, update.pos) {
+            continue;
+        }
 
-        let local = {
+        let (local, biome_colors) = {
             let world = update.world.read();
             let chunk = if let Some(chunk) = world.chunks.get(&update.pos) {
                 chunk.read().clone()
@@ -164,300 +976,867 @@ async fn create_meshes_task(
                 continue;
             };
 
-            ChunkLocal {
+            let lights = lights.0.read();
+            let neighbour_pos =
+                |i| update.pos + index_to_offset(i).expect("index should always be less then 8");
+
+            let local = ChunkLocal {
                 chunk,
                 neighbers: array::from_fn(|i| {
                     world
                         .chunks
-                        .get(
-                            &(update.pos
-                                + index_to_offset(i).expect("index should always be less then 8")),
-                        )
+                        .get(&neighbour_pos(i))
                         .map(|c| c.read().clone())
                 }),
-            }
+                light: lights
+                    .get(&(update.instance_name.clone(), update.pos))
+                    .cloned()
+                    .unwrap_or_default(),
+                neighbour_lights: array::from_fn(|i| {
+                    lights
+                        .get(&(update.instance_name.clone(), neighbour_pos(i)))
+                        .cloned()
+                }),
+                has_sky_light: update.has_sky_light,
+                min_y: update.min_y,
+            };
+
+            let biome_colors = world_biome_colors
+                .0
+                .write()
+                .entry(update.instance_name.clone())
+                .or_insert_with(|| Arc::new(BiomeColors::new(&world.registries, colormaps.clone())))
+                .clone();
+
+            (Arc::new(local), biome_colors)
         };
 
-        for y in (-64..(320)).step_by(16) {
-            let pos = ChunkSectionPos::new(update.pos.x, y / 16 as i32, update.pos.z);
-
-            let render_chunk = mesh_section(pos, &local);
-            mesh_send
-                .send((
-                    Transform::from_xyz(
-                        (pos.x * 16) as f32,
-                        (pos.y * 16) as f32,
-                        (pos.z * 16) as f32,
-                    ),
-                    render_chunk,
-                ))
-                .expect("Client disconnected, panicing.");
-        }
-
-        info!(
-            "Meshing chunk took: {}, with pos: {:?}",
-            time.elapsed().as_secs_f32(),
-            update.pos
-        );
+        let sections: Vec<i32> = match &update.sections {
+            Some(sections) => sections.iter().copied().collect(),
+            None => update.section_range().collect(),
+        };
+        for y in sections {
+            slots_send
+                .send_async(())
+                .await
+                .expect("the task should hold a receiver");
+            let slot = JobSlot(slots_recv.clone());
+
+            // The chunk can be unloaded while its sections are waiting for a job.
+            if !context.loaded.contains(&update.instance_name, update.pos) {
+                break;
+            }
+            let pos = ChunkSectionPos::new(update.pos.x, y, update.pos.z);
+            generation += 1;
+
+            let context = context.clone();
+            let local = local.clone();
+            let biome_colors = biome_colors.clone();
+            let instance_name = update.instance_name.clone();
+            let constant_ambient_light = update.constant_ambient_light;
+            thread_pool
+                .spawn(async move {
+                    let _slot = slot;
+
+                    let mut blender = BiomeBlender::new(&local, &biome_colors, context.biome_blend);
+                    let mesh = mesh_section(
+                        pos,
+                        &local,
+                        &context.models,
+                        &context.textures,
+                        &mut blender,
+                        constant_ambient_light,
+                    );
+                    // The receiver is only dropped when the app exits.
+                    let _ = context.mesh_send.send(SectionMesh {
+                        instance_name,
+                        pos,
+                        mesh,
+                        missing_neighbours: local.missing_neighbours(),
+                        generation,
+                    });
+                })
+                .detach();
+        }
     }
 }
 
-pub fn mesh_section(pos: ChunkSectionPos, update: &ChunkLocal) -> Mesh {
-    let mut vertices: Vec<[f32; 3]> = Vec::new();
-    let mut indices = Vec::new();
+pub fn mesh_section(
+    pos: ChunkSectionPos,
+    update: &ChunkLocal,
+    models: &BlockStateModels,
+    textures: &TextureIdMap,
+    blender: &mut BiomeBlender,
+    constant_ambient_light: bool,
+) -> Option<Mesh> {
+    let mut builder = SectionMeshBuilder::default();
 
-    // Iterate over the chunk's blocks and generate mesh vertices
-    //
     for y in 0..16 {
         for x in 0..16 {
             for z in 0..16 {
                 let block_pos = BlockPos::new(x, y + pos.y * 16, z);
 
-                if !update.get_block(block_pos).is_some_and(|b| b.is_air()) {
-                    for face in FACES {
-                        indices.extend_from_slice(&[
-                            vertices.len() as u16 + 0,
-                            vertices.len() as u16 + 1,
-                            vertices.len() as u16 + 2,
-                            vertices.len() as u16 + 0,
-                            vertices.len() as u16 + 2,
-                            vertices.len() as u16 + 3,
-                        ]);
-                        let normal = face.dir.normal();
-                        let normal = IVec3::new(
-                            normal.x.round() as i32,
-                            normal.y.round() as i32,
-                            normal.z.round() as i32,
-                        );
-
-                        for offset in face.offsets {
-                            let neighbor = BlockPos::new(x + normal.x, y + normal.y, z + normal.z);
-
-                            if !update.get_block(neighbor).is_some_and(|b| b.is_air()) {
-                                continue;
-                            }
-
-                            vertices.push(
-                                (offset + glam::IVec3::new(x as i32, y as i32, z as i32))
-                                    .as_vec3()
-                                    .into(),
-                            );
+                let Some(state) = update.get_block(block_pos) else {
+                    continue;
+                };
+                if state.is_air() {
+                    continue;
+                }
+
+                let model = models.get(state);
+
+                let neighbour = |direction| {
+                    update
+                        .get_block(block_pos + direction_offset(direction))
+                        .map(|neighbour| models.get(neighbour))
+                };
+
+                // Fast path for the common case of a full block buried in opaque full blocks.
+                if model.full_cube
+                    && DIRECTIONS.into_iter().all(|direction| {
+                        neighbour(direction).is_some_and(|neighbour| {
+                            neighbour.occlusion[direction_index(opposite_direction(direction))]
+                                == FaceMask::FULL
+                        })
+                    })
+                {
+                    continue;
+                }
+
+                let world_pos = BlockPos::new(pos.x * 16 + x, block_pos.y, pos.z * 16 + z);
+                let offset = glam::Vec3::new(x as f32, y as f32, z as f32);
+
+                let tint = match model.tint {
+                    Tint::None => None,
+                    tint => Some(blender.color(tint, block_pos)),
+                };
+
+                for baked in model.models(world_pos) {
+                    for quad in &baked.quads {
+                        // Faces next to unloaded blocks are kept, they get culled once the
+                        // section is meshed again with its neighbours.
+                        let culled = quad.cullface.is_some_and(|cullface| {
+                            neighbour(cullface).is_some_and(|neighbour| {
+                                let side = direction_index(opposite_direction(cullface));
+                                quad.cull_mask.is_covered_by(neighbour.occlusion[side])
+                                    || (model.cull_same_block
+                                        && neighbour.block == model.block
+                                        && quad.cull_mask.is_covered_by(neighbour.faces[side]))
+                            })
+                        });
+                        if culled {
+                            continue;
                         }
+
+                        let opaque = |offset: glam::IVec3| {
+                            update
+                                .get_block(block_pos + BlockPos::new(offset.x, offset.y, offset.z))
+                                .is_some_and(|block| models.get(block).is_opaque_cube())
+                        };
+                        // Blocks in chunks that aren't loaded count as unlit, which smooth
+                        // lighting leaves out.
+                        let light = |offset: glam::IVec3| {
+                            update
+                                .get_light(block_pos + BlockPos::new(offset.x, offset.y, offset.z))
+                                .unwrap_or((0, 0))
+                        };
+
+                        // Like vanilla, only models with ambient occlusion are lit smoothly.
+                        let (ao, lights) = if baked.ambient_occlusion {
+                            (quad_ao(quad, opaque), quad_light(quad, light, opaque))
+                        } else {
+                            ([3; 4], [flat_light(quad, light); 4])
+                        };
+
+                        let tint = quad.tint_index.and(tint).unwrap_or(0xffffff);
+                        let shade = shade(quad.direction, quad.shade, constant_ambient_light);
+                        let colors =
+                            ao.map(|ao| vertex_color(tint, shade * AO_BRIGHTNESS[ao as usize]));
+
+                        builder.push_quad(offset, quad, textures, colors, lights, ao);
                     }
                 }
             }
         }
     }
-    let mut mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::RENDER_WORLD,
-    );
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
-    mesh.insert_indices(Indices::U16(indices));
-
-    mesh
-}
-
-fn generate_uv(dir: Direction, uvs: Option<[f32; 4]>) -> [glam::Vec2; 4] {
-    match uvs {
-        Some(uvs) => match dir {
-            Direction::Up => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-            ],
-            Direction::Down => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-            ],
-            Direction::North => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-            ],
-            Direction::South => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-            ],
-            Direction::East => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-            ],
-            Direction::West => [
-                glam::Vec2::new(uvs[0] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[3] / 16.0),
-                glam::Vec2::new(uvs[2] / 16.0, uvs[1] / 16.0),
-                glam::Vec2::new(uvs[0] / 16.0, uvs[1] / 16.0),
-            ],
-        },
-        None => match dir {
-            Direction::Up => [
-                glam::Vec2::new(0.0, 0.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(1.0, 1.0),
-                glam::Vec2::new(0.0, 1.0),
-            ],
-            Direction::Down => [
-                glam::Vec2::new(0.0, 0.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(1.0, 1.0),
-                glam::Vec2::new(0.0, 1.0),
-            ],
-            Direction::North => [
-                glam::Vec2::new(0.0, 1.0),
-                glam::Vec2::new(0.0, 0.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(1.0, 1.0),
-            ],
-            Direction::South => [
-                glam::Vec2::new(0.0, 1.0),
-                glam::Vec2::new(1.0, 1.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(0.0, 0.0),
-            ],
-            Direction::East => [
-                glam::Vec2::new(0.0, 1.0),
-                glam::Vec2::new(0.0, 0.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(1.0, 1.0),
-            ],
-            Direction::West => [
-                glam::Vec2::new(0.0, 1.0),
-                glam::Vec2::new(1.0, 1.0),
-                glam::Vec2::new(1.0, 0.0),
-                glam::Vec2::new(0.0, 0.0),
-            ],
-        },
+    builder.build()
+}
+
+/// How much a face is darkened depending on its direction, like vanilla's `ClientLevel::getShade`.
+fn shade(direction: Direction, shade: bool, constant_ambient_light: bool) -> f32 {
+    if !shade {
+        return if constant_ambient_light { 0.9 } else { 1.0 };
+    }
+
+    match direction {
+        Direction::Down if constant_ambient_light => 0.9,
+        Direction::Down => 0.5,
+        Direction::Up if constant_ambient_light => 0.9,
+        Direction::Up => 1.0,
+        Direction::North | Direction::South => 0.8,
+        Direction::West | Direction::East => 0.6,
     }
 }
 
-fn compute_ao(
-    pos: BlockPos,
-    offset: glam::IVec3,
-    face_normal: glam::IVec3,
-    update: &ChunkLocal,
-) -> u32 {
-    let ao = if face_normal.x != 0 {
-        let side1 = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, 0, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        let side2 = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, offset.y * 2 - 1, 0))
-            .is_some_and(|b| b.is_shape_full());
-
-        let corner = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, offset.y * 2 - 1, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        ao(side1, side2, corner)
-    } else if face_normal.y != 0 {
-        let side1 = update
-            .get_block(pos + BlockPos::new(0, offset.y * 2 - 1, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        let side2 = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, offset.y * 2 - 1, 0))
-            .is_some_and(|b| b.is_shape_full());
-
-        let corner = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, offset.y * 2 - 1, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        ao(side1, side2, corner)
-    } else {
-        let side1 = update
-            .get_block(pos + BlockPos::new(0, offset.y * 2 - 1, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        let side2 = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, 0, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        let corner = update
-            .get_block(pos + BlockPos::new(offset.x * 2 - 1, offset.y * 2 - 1, offset.z * 2 - 1))
-            .is_some_and(|b| b.is_shape_full());
-
-        ao(side1, side2, corner)
-    };
+/// Vertex colors are multiplied with the texture in linear space, which for the sRGB transfer
+/// function is close to multiplying them in sRGB space like vanilla does.
+fn vertex_color(tint: u32, shade: f32) -> [f32; 4] {
+    let channel = |shift: u32| ((tint >> shift) & 0xff) as f32 / 255.0 * shade;
+    Color::rgb(channel(16), channel(8), channel(0)).as_linear_rgba_f32()
+}
 
-    ao
+fn direction_offset(direction: Direction) -> BlockPos {
+    let normal = direction_normal(direction).as_ivec3();
+    BlockPos::new(normal.x, normal.y, normal.z)
 }
 
-fn ao(side1: bool, side2: bool, corner: bool) -> u32 {
-    if side1 && side2 {
-        0
-    } else {
-        3 - ((side1 || side2) as u32 + corner as u32)
+#[derive(Default)]
+struct SectionMeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    lights: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl SectionMeshBuilder {
+    fn push_quad(
+        &mut self,
+        offset: glam::Vec3,
+        quad: &BakedQuad,
+        textures: &TextureIdMap,
+        colors: [[f32; 4]; 4],
+        lights: [(f32, f32); 4],
+        ao: [u8; 4],
+    ) {
+        let start = self.positions.len() as u32;
+        self.indices
+            .extend(quad_indices(ao).map(|index| start + index));
+
+        let normal = direction_normal(quad.direction);
+        for (((position, uv), color), (sky, block)) in
+            quad.positions.iter().zip(quad.uvs).zip(colors).zip(lights)
+        {
+            self.positions.push((offset + *position).into());
+            self.normals.push(normal.into());
+            self.uvs.push(textures.atlas_uv(quad.texture, uv).into());
+            self.colors.push(color);
+            self.lights.push([sky / 15.0, block / 15.0]);
+        }
+    }
+
+    /// Builds the mesh, or returns `None` if no quads were pushed.
+    fn build(self) -> Option<Mesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_attribute(ATTRIBUTE_LIGHT, self.lights);
+        mesh.insert_indices(Indices::U32(self.indices));
+
+        Some(mesh)
     }
 }
-struct Face {
-    offsets: [IVec3; 4],
-    dir: Direction,
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashMap, HashSet},
+        sync::Arc,
+    };
+
+    use azalea_block::BlockState;
+    use azalea_core::{
+        direction::Direction,
+        position::{BlockPos, ChunkBlockPos, ChunkPos, ChunkSectionPos},
+        registry_holder::RegistryHolder,
+        resource_location::ResourceLocation,
+    };
+    use bevy::{
+        ecs::entity::Entity,
+        render::mesh::{Mesh, VertexAttributeValues},
+    };
+    use image::RgbaImage;
+
+    use super::{
+        mesh_section, shade, vertex_color, ChunkLocal, DirtySections, JobSlot, SectionEntities,
+    };
+    use crate::renderer::{
+        assets::{
+            baked::{
+                bake_model, nearest_direction, BakedModel, BlockStateModel, BlockStateModels,
+                ModelRotation, WeightedModel, DIRECTIONS,
+            },
+            model::BlockModel,
+            texture::AtlasRect,
+            tint::{Colormaps, Tint},
+            BlockModelRef, TextureIdMap,
+        },
+        biome::{BiomeBlender, BiomeColors},
+        light::ChunkLight,
+    };
+
+    const MIN_Y: i32 = -64;
+
+    // The block state ids the test blocks are given, in the order of `test_models`.
+    const STONE: u32 = 1;
+    const SLAB: u32 = 2;
+    const TORCH: u32 = 3;
+    const GLASS: u32 = 4;
+    const LEAVES: u32 = 5;
+    const PANE: u32 = 6;
+
+    const CUBE: &str = r##"
+{
+    "textures": { "all": "block/stone" },
+    "elements": [
+        {   "from": [ 0, 0, 0 ],
+            "to": [ 16, 16, 16 ],
+            "faces": {
+                "down":  { "texture": "#all", "cullface": "down" },
+                "up":    { "texture": "#all", "cullface": "up" },
+                "north": { "texture": "#all", "cullface": "north" },
+                "south": { "texture": "#all", "cullface": "south" },
+                "west":  { "texture": "#all", "cullface": "west" },
+                "east":  { "texture": "#all", "cullface": "east" }
+            }
+        }
+    ]
 }
+    "##;
 
-const FACES: [Face; 6] = [
-    Face {
-        offsets: [
-            glam::IVec3::new(0, 1, 0),
-            glam::IVec3::new(0, 1, 1),
-            glam::IVec3::new(1, 1, 1),
-            glam::IVec3::new(1, 1, 0),
-        ],
-        dir: Direction::Up,
-    },
-    Face {
-        offsets: [
-            glam::IVec3::new(0, 0, 0),
-            glam::IVec3::new(1, 0, 0),
-            glam::IVec3::new(1, 0, 1),
-            glam::IVec3::new(0, 0, 1),
-        ],
-        dir: Direction::Down,
-    },
-    Face {
-        offsets: [
-            glam::IVec3::new(0, 0, 1),
-            glam::IVec3::new(1, 0, 1),
-            glam::IVec3::new(1, 1, 1),
-            glam::IVec3::new(0, 1, 1),
-        ],
-        dir: Direction::South,
-    },
-    Face {
-        offsets: [
-            glam::IVec3::new(0, 0, 0),
-            glam::IVec3::new(0, 1, 0),
-            glam::IVec3::new(1, 1, 0),
-            glam::IVec3::new(1, 0, 0),
-        ],
-        dir: Direction::North,
-    },
-    Face {
-        offsets: [
-            glam::IVec3::new(1, 0, 0),
-            glam::IVec3::new(1, 1, 0),
-            glam::IVec3::new(1, 1, 1),
-            glam::IVec3::new(1, 0, 1),
-        ],
-        dir: Direction::East,
-    },
-    Face {
-        offsets: [
-            glam::IVec3::new(0, 0, 0),
-            glam::IVec3::new(0, 0, 1),
-            glam::IVec3::new(0, 1, 1),
-            glam::IVec3::new(0, 1, 0),
-        ],
-        dir: Direction::West,
-    },
-];
+    const SLAB_MODEL: &str = r##"
+{
+    "textures": { "all": "block/stone" },
+    "elements": [
+        {   "from": [ 0, 0, 0 ],
+            "to": [ 16, 8, 16 ],
+            "faces": {
+                "down":  { "texture": "#all", "cullface": "down" },
+                "up":    { "texture": "#all" },
+                "north": { "texture": "#all", "cullface": "north" },
+                "south": { "texture": "#all", "cullface": "south" },
+                "west":  { "texture": "#all", "cullface": "west" },
+                "east":  { "texture": "#all", "cullface": "east" }
+            }
+        }
+    ]
+}
+    "##;
+
+    const TORCH_MODEL: &str = r##"
+{
+    "ambientocclusion": false,
+    "textures": { "torch": "block/torch" },
+    "elements": [
+        {   "from": [ 7, 0, 7 ],
+            "to": [ 9, 10, 9 ],
+            "shade": false,
+            "faces": {
+                "down": { "uv": [ 7, 13, 9, 15 ], "texture": "#torch", "cullface": "down" },
+                "up":   { "uv": [ 7,  6, 9,  8 ], "texture": "#torch" }
+            }
+        },
+        {   "from": [ 7, 0, 0 ],
+            "to": [ 9, 16, 16 ],
+            "shade": false,
+            "faces": {
+                "west": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" },
+                "east": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" }
+            }
+        },
+        {   "from": [ 0, 0, 7 ],
+            "to": [ 16, 16, 9 ],
+            "shade": false,
+            "faces": {
+                "north": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" },
+                "south": { "uv": [ 0, 0, 16, 16 ], "texture": "#torch" }
+            }
+        }
+    ]
+}
+    "##;
+
+    /// The post of a glass pane, without any of its sides.
+    const PANE_MODEL: &str = r##"
+{
+    "textures": { "pane": "block/glass" },
+    "elements": [
+        {   "from": [ 7, 0, 7 ],
+            "to": [ 9, 16, 9 ],
+            "faces": {
+                "down":  { "texture": "#pane", "cullface": "down" },
+                "up":    { "texture": "#pane", "cullface": "up" },
+                "north": { "texture": "#pane" },
+                "south": { "texture": "#pane" },
+                "west":  { "texture": "#pane" },
+                "east":  { "texture": "#pane" }
+            }
+        }
+    ]
+}
+    "##;
+
+    /// Bakes a model whose textures are all opaque or all transparent.
+    fn bake(json: &str, opaque: bool, textures: &mut TextureIdMap) -> Arc<BakedModel> {
+        let model = BlockModel::from_str(json).unwrap();
+        let model = BlockModelRef {
+            ambient_occlusion: model.ambientocclusion.unwrap_or(true),
+            parent: None,
+            textures: &model.textures,
+            elements: &model.elements,
+        };
+
+        let mut baked = bake_model(&model, ModelRotation::default(), false, textures);
+        baked.occlusion = baked.occluding_faces(|_| opaque);
+        Arc::new(baked)
+    }
+
+    fn block(id: &'static str, model: Arc<BakedModel>) -> BlockStateModel {
+        let part = WeightedModel {
+            models: vec![(1, model)],
+            total_weight: 1,
+        };
+        BlockStateModel::new(id, Tint::None, vec![part], false)
+    }
+
+    /// The models of the test blocks, every other block state has none. Every texture gets its
+    /// own 16x16 slot in a row of the atlas.
+    fn test_models() -> (BlockStateModels, TextureIdMap) {
+        let mut textures = TextureIdMap::default();
+        let mut blocks = vec![
+            block("stone", bake(CUBE, true, &mut textures)),
+            block("smooth_stone_slab", bake(SLAB_MODEL, true, &mut textures)),
+            block("torch", bake(TORCH_MODEL, false, &mut textures)),
+            block("glass", bake(CUBE, false, &mut textures)),
+            block("oak_leaves", bake(CUBE, false, &mut textures)),
+            block("glass_pane", bake(PANE_MODEL, false, &mut textures)),
+        ]
+        .into_iter();
+
+        let models = (0..=BlockState::max_state())
+            .map(|id| match id {
+                0 => BlockStateModel::default(),
+                _ => blocks.next().unwrap_or_default(),
+            })
+            .collect();
+
+        let rects = (0..textures.len() as u32)
+            .map(|i| AtlasRect {
+                x: i * 16,
+                y: 0,
+                width: 16,
+                height: 16,
+            })
+            .collect();
+        textures.set_atlas((textures.len() as u32 * 16, 16), rects);
+
+        (models, textures)
+    }
+
+    /// A chunk with `blocks` in it, and none of its neighbours loaded.
+    fn test_chunk(blocks: &[(BlockPos, u32)]) -> ChunkLocal {
+        let mut chunk = azalea_world::Chunk::default();
+        for &(pos, id) in blocks {
+            chunk.set(
+                &ChunkBlockPos::from(pos),
+                BlockState::try_from(id).unwrap(),
+                MIN_Y,
+            );
+        }
+
+        ChunkLocal {
+            chunk,
+            neighbers: Default::default(),
+            light: ChunkLight::default(),
+            neighbour_lights: Default::default(),
+            has_sky_light: true,
+            min_y: MIN_Y,
+        }
+    }
+
+    /// Meshes the section at the bottom of y 0.
+    fn mesh(blocks: &[(BlockPos, u32)]) -> Option<Mesh> {
+        let (models, textures) = test_models();
+        let local = test_chunk(blocks);
+        let colors = BiomeColors::new(
+            &RegistryHolder::default(),
+            Colormaps::new(RgbaImage::new(0, 0), RgbaImage::new(0, 0)),
+        );
+        let mut blender = BiomeBlender::new(&local, &colors, 0);
+
+        mesh_section(
+            ChunkSectionPos::new(0, 0, 0),
+            &local,
+            &models,
+            &textures,
+            &mut blender,
+            false,
+        )
+    }
+
+    /// A vertex of a section mesh.
+    #[derive(Debug)]
+    struct Vertex {
+        position: glam::Vec3,
+        normal: glam::Vec3,
+        uv: glam::Vec2,
+        color: [f32; 4],
+    }
+
+    fn vertices(mesh: &Mesh) -> Vec<Vertex> {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(colors)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR),
+        )
+        else {
+            panic!("section meshes should have positions, normals, uvs and colors");
+        };
+
+        (0..positions.len())
+            .map(|i| Vertex {
+                position: positions[i].into(),
+                normal: normals[i].into(),
+                uv: uvs[i].into(),
+                color: colors[i],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lone_block_test() {
+        let mesh = mesh(&[(BlockPos::new(8, 4, 8), STONE)]).unwrap();
+        assert_eq!(mesh.count_vertices(), 24);
+        assert_eq!(mesh.indices().unwrap().len(), 36);
+
+        let vertices = vertices(&mesh);
+        for direction in DIRECTIONS {
+            let face: Vec<_> = vertices
+                .iter()
+                .filter(|vertex| nearest_direction(vertex.normal) == direction)
+                .collect();
+            assert_eq!(face.len(), 4, "{direction:?}");
+
+            for vertex in face {
+                let min = glam::Vec3::new(8.0, 4.0, 8.0);
+                assert!(vertex.position.cmpge(min).all());
+                assert!(vertex.position.cmple(min + 1.0).all());
+                // Stone is the first texture, in the left half of the atlas.
+                assert!((0.0..=0.5).contains(&vertex.uv.x));
+                assert!((0.0..=1.0).contains(&vertex.uv.y));
+                // Nothing casts ambient occlusion on a lone block, so faces are only shaded by
+                // their direction.
+                assert_eq!(
+                    vertex.color,
+                    vertex_color(0xffffff, shade(direction, true, false))
+                );
+            }
+        }
+
+        // Every corner of the stone texture is used once per face.
+        let corners = vertices
+            .iter()
+            .filter(|vertex| [0.0, 0.5].contains(&vertex.uv.x) && [0.0, 1.0].contains(&vertex.uv.y))
+            .count();
+        assert_eq!(corners, 24);
+    }
+
+    #[test]
+    fn adjacent_blocks_test() {
+        let mesh = mesh(&[
+            (BlockPos::new(8, 4, 8), STONE),
+            (BlockPos::new(9, 4, 8), STONE),
+        ])
+        .unwrap();
+        // The faces between the two blocks are culled.
+        assert_eq!(mesh.count_vertices(), 40);
+        assert!(vertices(&mesh).iter().all(|vertex| {
+            !matches!(
+                nearest_direction(vertex.normal),
+                Direction::West | Direction::East
+            ) || vertex.position.x != 9.0
+        }));
+
+        // A block surrounded on every side is skipped entirely, only the outside of the 3x3x3
+        // cube is left.
+        let mut blocks = Vec::new();
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    blocks.push((BlockPos::new(x + 4, y + 4, z + 4), STONE));
+                }
+            }
+        }
+        let mesh = self::mesh(&blocks).unwrap();
+        assert_eq!(mesh.count_vertices(), 6 * 9 * 4);
+    }
+
+    #[test]
+    fn non_cube_test() {
+        // The top of a slab isn't culled by anything, its bottom is culled by the stone below.
+        let mesh = mesh(&[
+            (BlockPos::new(8, 4, 8), STONE),
+            (BlockPos::new(8, 5, 8), SLAB),
+        ])
+        .unwrap();
+        assert_eq!(mesh.count_vertices(), (5 + 5) * 4);
+        let vertices = vertices(&mesh);
+        let top: Vec<_> = vertices
+            .iter()
+            .filter(|vertex| nearest_direction(vertex.normal) == Direction::Up)
+            .collect();
+        assert_eq!(top.len(), 4);
+        assert!(top.iter().all(|vertex| vertex.position.y == 5.5));
+
+        // Torches are made of planes inside the block, and aren't shaded.
+        let mesh = self::mesh(&[(BlockPos::new(8, 5, 8), TORCH)]).unwrap();
+        assert_eq!(mesh.count_vertices(), 6 * 4);
+        let torch = self::vertices(&mesh);
+        assert!(torch.iter().all(|vertex| {
+            vertex.color == vertex_color(0xffffff, 1.0)
+                && vertex.uv.x >= 0.5
+                && vertex.position.y >= 5.0
+        }));
+        assert!(torch
+            .iter()
+            .any(|vertex| vertex.position.x == 8.0 + 7.0 / 16.0));
+    }
+
+    #[test]
+    fn cull_test() {
+        let pair = |a, b| {
+            let mesh = mesh(&[(BlockPos::new(8, 4, 8), a), (BlockPos::new(9, 4, 8), b)]);
+            mesh.unwrap().count_vertices() / 4
+        };
+
+        // Transparent blocks hide the faces between two blocks of the same kind, and the faces
+        // opaque blocks cover, but don't hide anything behind them.
+        assert_eq!(pair(GLASS, GLASS), 10);
+        assert_eq!(pair(GLASS, STONE), 11);
+        // Like vanilla, leaves keep the faces between them.
+        assert_eq!(pair(LEAVES, LEAVES), 12);
+        assert_eq!(pair(LEAVES, STONE), 11);
+        // The side of the slab is hidden by the full block, but it only covers half of the full
+        // block's side.
+        assert_eq!(pair(SLAB, STONE), 11);
+
+        // Panes aren't full blocks, but the faces they share are hidden all the same.
+        let mesh = mesh(&[
+            (BlockPos::new(8, 4, 8), PANE),
+            (BlockPos::new(8, 5, 8), PANE),
+        ])
+        .unwrap();
+        assert_eq!(mesh.count_vertices() / 4, 10);
+    }
+
+    #[test]
+    fn shade_test() {
+        // The shade of shaded faces in the overworld and in the nether, where only the sides are
+        // darker. Faces that aren't shaded are only darkened in the nether.
+        let table = [
+            (Direction::Down, 0.5, 0.9),
+            (Direction::Up, 1.0, 0.9),
+            (Direction::North, 0.8, 0.8),
+            (Direction::South, 0.8, 0.8),
+            (Direction::West, 0.6, 0.6),
+            (Direction::East, 0.6, 0.6),
+        ];
+        for (direction, overworld, nether) in table {
+            assert_eq!(shade(direction, true, false), overworld, "{direction:?}");
+            assert_eq!(shade(direction, true, true), nether, "{direction:?}");
+            assert_eq!(shade(direction, false, false), 1.0, "{direction:?}");
+            assert_eq!(shade(direction, false, true), 0.9, "{direction:?}");
+        }
+    }
+
+    #[test]
+    fn dirty_block_test() {
+        let dirty = |pos| {
+            let mut dirty = DirtySections::default();
+            dirty.insert_block(pos);
+            dirty.0
+        };
+
+        // A block inside a section only changes that section.
+        assert_eq!(
+            dirty(BlockPos::new(8, 40, 8)),
+            HashMap::from([(ChunkPos::new(0, 0), BTreeSet::from([2]))])
+        );
+
+        // A block on the edge of a section changes the faces of the section next to it.
+        assert_eq!(
+            dirty(BlockPos::new(15, 40, 8)),
+            HashMap::from([
+                (ChunkPos::new(0, 0), BTreeSet::from([2])),
+                (ChunkPos::new(1, 0), BTreeSet::from([2])),
+            ])
+        );
+
+        // A block in the corner of a section changes the eight sections around the corner.
+        let corner = BTreeSet::from([1, 2]);
+        assert_eq!(
+            dirty(BlockPos::new(0, 32, 0)),
+            HashMap::from([
+                (ChunkPos::new(-1, -1), corner.clone()),
+                (ChunkPos::new(-1, 0), corner.clone()),
+                (ChunkPos::new(0, -1), corner.clone()),
+                (ChunkPos::new(0, 0), corner),
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_neighbours_test() {
+        let overworld = ResourceLocation::new("minecraft:overworld");
+        let nether = ResourceLocation::new("minecraft:the_nether");
+        let mut entities = SectionEntities::default();
+
+        // Bits indexed like `index_to_offset`.
+        let (north, east, northeast) = (1 << 0, 1 << 2, 1 << 4);
+        entities.set_missing_neighbours(&overworld, ChunkSectionPos::new(0, 4, 0), north | east);
+        entities.set_missing_neighbours(&overworld, ChunkSectionPos::new(0, 5, 0), north);
+        entities.set_missing_neighbours(&overworld, ChunkSectionPos::new(0, 6, 0), northeast);
+        // Meshed again once its neighbour was loaded, so it has nothing left to wait for.
+        entities.set_missing_neighbours(&overworld, ChunkSectionPos::new(4, 4, 4), east);
+        entities.set_missing_neighbours(&overworld, ChunkSectionPos::new(4, 4, 4), 0);
+
+        assert!(entities
+            .take_meshed_without(&overworld, ChunkPos::new(5, 4))
+            .is_empty());
+        assert!(entities
+            .take_meshed_without(&nether, ChunkPos::new(0, -1))
+            .is_empty());
+
+        // The sections that were meshed without the chunk are meshed again when it arrives, and
+        // only once.
+        let mut taken = entities.take_meshed_without(&overworld, ChunkPos::new(0, -1));
+        taken.sort_by_key(|pos| pos.y);
+        assert_eq!(
+            taken,
+            [ChunkSectionPos::new(0, 4, 0), ChunkSectionPos::new(0, 5, 0)]
+        );
+        assert!(entities
+            .take_meshed_without(&overworld, ChunkPos::new(0, -1))
+            .is_empty());
+
+        assert_eq!(
+            entities.take_meshed_without(&overworld, ChunkPos::new(1, -1)),
+            [ChunkSectionPos::new(0, 6, 0)]
+        );
+        assert_eq!(
+            entities.take_meshed_without(&overworld, ChunkPos::new(1, 0)),
+            [ChunkSectionPos::new(0, 4, 0)]
+        );
+        assert!(entities.missing_neighbours.is_empty());
+    }
+
+    #[test]
+    fn remove_chunks_test() {
+        let overworld = ResourceLocation::new("minecraft:overworld");
+        let nether = ResourceLocation::new("minecraft:the_nether");
+        let mut entities = SectionEntities::default();
+
+        let sections = [
+            (overworld.clone(), ChunkSectionPos::new(0, 4, 0)),
+            (overworld.clone(), ChunkSectionPos::new(0, 5, 0)),
+            (overworld.clone(), ChunkSectionPos::new(1, 4, 0)),
+            (nether.clone(), ChunkSectionPos::new(0, 4, 0)),
+        ];
+        for (i, (instance_name, pos)) in sections.iter().enumerate() {
+            entities
+                .entities
+                .insert((instance_name.clone(), *pos), Entity::from_raw(i as u32));
+            // Every section is waiting for its north neighbour.
+            entities.set_missing_neighbours(instance_name, *pos, 1);
+        }
+
+        let mut removed = entities.remove_chunks(&overworld, &HashSet::from([ChunkPos::new(0, 0)]));
+        removed.sort();
+        assert_eq!(removed, [Entity::from_raw(0), Entity::from_raw(1)]);
+        assert_eq!(
+            entities.get(&overworld, ChunkSectionPos::new(0, 4, 0)),
+            None
+        );
+        assert_eq!(
+            entities.get(&overworld, ChunkSectionPos::new(1, 4, 0)),
+            Some(Entity::from_raw(2))
+        );
+        assert_eq!(
+            entities.get(&nether, ChunkSectionPos::new(0, 4, 0)),
+            Some(Entity::from_raw(3))
+        );
+
+        // The forgotten chunk isn't meshed again when its neighbour arrives, the others are.
+        assert!(entities
+            .take_meshed_without(&overworld, ChunkPos::new(0, -1))
+            .is_empty());
+        assert_eq!(
+            entities.take_meshed_without(&overworld, ChunkPos::new(1, -1)),
+            [ChunkSectionPos::new(1, 4, 0)]
+        );
+        assert_eq!(
+            entities.take_meshed_without(&nether, ChunkPos::new(0, -1)),
+            [ChunkSectionPos::new(0, 4, 0)]
+        );
+    }
+
+    #[test]
+    fn generation_test() {
+        let overworld = ResourceLocation::new("minecraft:overworld");
+        let nether = ResourceLocation::new("minecraft:the_nether");
+        let pos = ChunkSectionPos::new(0, 4, 0);
+        let mut entities = SectionEntities::default();
+
+        assert!(entities.update_generation(&overworld, pos, 2));
+        // A mesh from a job that started earlier but finished later is stale.
+        assert!(!entities.update_generation(&overworld, pos, 1));
+        assert!(entities.update_generation(&overworld, pos, 3));
+        // Every section of every world has its own generation.
+        assert!(entities.update_generation(&overworld, ChunkSectionPos::new(1, 4, 0), 1));
+        assert!(entities.update_generation(&nether, pos, 1));
+
+        // Forgetting a chunk or leaving a world starts its sections over.
+        entities.remove_chunks(&overworld, &HashSet::from([ChunkPos::new(0, 0)]));
+        assert!(entities.update_generation(&overworld, pos, 1));
+        assert!(!entities.update_generation(&overworld, ChunkSectionPos::new(1, 4, 0), 0));
+        assert!(!entities.update_generation(&nether, pos, 0));
+
+        entities.remove_instance(&nether);
+        assert!(entities.update_generation(&nether, pos, 0));
+        assert!(!entities.update_generation(&overworld, pos, 0));
+    }
+
+    #[test]
+    fn job_slot_test() {
+        let (slots_send, slots_recv) = flume::bounded(2);
+
+        let mut slots = Vec::new();
+        for _ in 0..2 {
+            slots_send.try_send(()).unwrap();
+            slots.push(JobSlot(slots_recv.clone()));
+        }
+        // No more jobs can start until one of them is done.
+        assert!(slots_send.try_send(()).is_err());
+
+        slots.pop();
+        assert!(slots_send.try_send(()).is_ok());
+    }
+}
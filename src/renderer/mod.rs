@@ -3,17 +3,39 @@ use std::{
     sync::Arc,
 };
 
-//pub mod assets;
+mod ao;
+pub mod assets;
+mod biome;
 //mod chunk;
+mod light;
+mod material;
 mod mesher;
+mod queue;
 //mod world;
 
+use azalea_core::tick::GameTick;
 use bevy::{
-    app::{App, Plugin, PluginGroup, Startup},
-    asset::{io::AssetSourceId, AssetServer, Handle},
-    ecs::system::{Commands, Res, Resource},
-    log::LogPlugin,
-    render::{camera::ClearColor, color::Color, texture::Image},
+    app::{App, First, Plugin, PluginGroup, Startup},
+    asset::{io::AssetSourceId, AssetServer, Assets, Handle},
+    diagnostic::LogDiagnosticsPlugin,
+    ecs::{
+        schedule::{common_conditions::resource_exists, IntoSystemConfigs},
+        system::{Commands, Res, ResMut, Resource},
+    },
+    log::{error, LogPlugin},
+    render::{
+        camera::ClearColor,
+        color::Color,
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureDimension,
+            TextureFormat,
+        },
+        renderer::RenderQueue,
+        texture::{Image, ImageFilterMode, ImageSampler, ImageSamplerDescriptor},
+        Render, RenderApp, RenderSet,
+    },
     tasks::{
         futures_lite::{FutureExt, StreamExt},
         AsyncComputeTaskPool, Task,
@@ -22,10 +44,47 @@ use bevy::{
     utils::BoxedFuture,
     DefaultPlugins,
 };
+use image::RgbaImage;
 
-use self::mesher::ChunkMeshPlugin;
+use self::{
+    assets::{
+        baked::BlockStateModels,
+        texture::{stitch_atlas, AnimatedTexture, AtlasRect, TextureImages},
+        tint::Colormaps,
+        LoadedAssets, TextureIdMap,
+    },
+    material::{ChunkMaterial, ChunkMaterialPlugin},
+    mesher::{ChunkMeshPlugin, MERGED_SECTIONS, MESHED_SECTIONS, QUEUED_SECTIONS},
+};
 
-pub struct RenderPlugin {}
+pub struct RenderPlugin {
+    /// The directory the block models, blockstates and textures are loaded from, like the
+    /// `assets/minecraft` directory of a resource pack.
+    pub assets_path: PathBuf,
+    /// How many mip levels the block atlas gets, vanilla's mipmap levels option.
+    pub mipmap_levels: u32,
+    /// How many blocks around a block biome colors are averaged over, from 0 to 7 like vanilla's
+    /// biome blend option.
+    pub biome_blend: u32,
+    /// How many sections are meshed at once at most, the other queued sections wait for one of
+    /// them to finish.
+    pub max_meshing_jobs: usize,
+    /// Whether to log how many sections are queued, meshed and merged in the mesh queue every
+    /// second. On by default in debug builds.
+    pub log_mesh_queue: bool,
+}
+
+impl Default for RenderPlugin {
+    fn default() -> Self {
+        Self {
+            assets_path: PathBuf::from("assets/minecraft"),
+            mipmap_levels: 4,
+            biome_blend: 2,
+            max_meshing_jobs: 16,
+            log_mesh_queue: cfg!(debug_assertions),
+        }
+    }
+}
 
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
@@ -36,7 +95,195 @@ impl Plugin for RenderPlugin {
                 .disable::<TimePlugin>(),
         )
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
-        .add_plugins(ChunkMeshPlugin);
+        .insert_resource(AssetsPath(self.assets_path.clone()))
+        .insert_resource(MipmapLevels(self.mipmap_levels))
+        .insert_resource(BiomeBlend(self.biome_blend))
+        .insert_resource(MaxMeshingJobs(self.max_meshing_jobs))
+        .add_systems(Startup, bake_models_system)
+        .add_systems(
+            First,
+            clear_atlas_updates_system.run_if(resource_exists::<AtlasUpdates>),
+        )
+        .add_systems(
+            GameTick,
+            animate_textures_system.run_if(resource_exists::<AnimatedTextures>),
+        )
+        .add_plugins((
+            ChunkMaterialPlugin,
+            ChunkMeshPlugin,
+            ExtractResourcePlugin::<AtlasUpdates>::default(),
+        ));
+
+        if self.log_mesh_queue {
+            app.add_plugins(LogDiagnosticsPlugin::filtered(vec![
+                QUEUED_SECTIONS,
+                MESHED_SECTIONS,
+                MERGED_SECTIONS,
+            ]));
+        }
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app.add_systems(
+                Render,
+                write_atlas_updates_system
+                    .run_if(resource_exists::<AtlasUpdates>)
+                    .in_set(RenderSet::PrepareResources),
+            );
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct AssetsPath(pub PathBuf);
+
+#[derive(Resource)]
+pub struct MipmapLevels(pub u32);
+
+#[derive(Resource)]
+pub struct BiomeBlend(pub u32);
+
+#[derive(Resource)]
+pub struct MaxMeshingJobs(pub usize);
+
+/// The material every chunk mesh is drawn with, samples the block texture atlas.
+#[derive(Resource)]
+pub struct BlockMaterial(pub Handle<ChunkMaterial>);
+
+fn bake_models_system(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+    assets_path: Res<AssetsPath>,
+    mipmap_levels: Res<MipmapLevels>,
+) {
+    // Without the models nothing is meshed, but the client keeps running.
+    let assets = match LoadedAssets::from_path(&assets_path.0) {
+        Ok(assets) => assets,
+        Err(err) => {
+            error!("could not load the block models: {}", err);
+            return;
+        }
+    };
+
+    let mut textures = TextureIdMap::default();
+    let mut images = TextureImages::default();
+    let models = BlockStateModels::bake(&assets, &mut textures, &mut images);
+    let (atlas, animated) = stitch_atlas(&assets, &mut textures, images, mipmap_levels.0);
+
+    let (width, height) = atlas.levels[0].dimensions();
+    let mut levels = atlas.levels.into_iter();
+    let mut atlas = Image::new(
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        levels.next().unwrap().into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    atlas.texture_descriptor.mip_level_count = mipmap_levels.0 + 1;
+    for level in levels {
+        atlas.data.extend_from_slice(&level);
+    }
+    atlas.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        mag_filter: ImageFilterMode::Nearest,
+        min_filter: ImageFilterMode::Nearest,
+        mipmap_filter: ImageFilterMode::Linear,
+        ..Default::default()
+    });
+
+    let atlas = images.add(atlas);
+    let material = materials.add(ChunkMaterial {
+        sky_brightness: 1.0,
+        atlas: atlas.clone(),
+    });
+
+    commands.insert_resource(BlockMaterial(material));
+    commands.insert_resource(AnimatedTextures(animated));
+    commands.insert_resource(AtlasUpdates {
+        atlas,
+        slots: Vec::new(),
+    });
+    commands.insert_resource(Colormaps::load(&assets));
+    commands.insert_resource(models);
+    commands.insert_resource(textures);
+}
+
+/// The animated textures in the block atlas.
+#[derive(Resource)]
+pub struct AnimatedTextures(Vec<AnimatedTexture>);
+
+/// The new mip levels of the atlas slots whose animation advanced this frame. They are written
+/// straight into the atlas texture in the render world, so the rest of the atlas isn't uploaded
+/// again.
+#[derive(Resource, Clone, ExtractResource)]
+pub struct AtlasUpdates {
+    atlas: Handle<Image>,
+    slots: Vec<(AtlasRect, Vec<RgbaImage>)>,
+}
+
+fn clear_atlas_updates_system(mut updates: ResMut<AtlasUpdates>) {
+    if !updates.slots.is_empty() {
+        updates.slots.clear();
+    }
+}
+
+fn animate_textures_system(
+    mut animated: ResMut<AnimatedTextures>,
+    mut updates: ResMut<AtlasUpdates>,
+) {
+    let frames: Vec<_> = animated
+        .0
+        .iter_mut()
+        .filter_map(|texture| Some((texture.slot, texture.tick()?)))
+        .collect();
+    // The game can tick more than once a frame, so this adds to the frame's updates.
+    if !frames.is_empty() {
+        updates.slots.extend(frames);
+    }
+}
+
+fn write_atlas_updates_system(
+    mut updates: ResMut<AtlasUpdates>,
+    images: Res<RenderAssets<Image>>,
+    queue: Res<RenderQueue>,
+) {
+    if updates.slots.is_empty() {
+        return;
+    }
+    let Some(atlas) = images.get(&updates.atlas) else {
+        return;
+    };
+
+    // Taken so they aren't written again on frames the main world's updates didn't change.
+    for (slot, levels) in std::mem::take(&mut updates.slots) {
+        for (level, image) in levels.into_iter().enumerate() {
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &atlas.texture,
+                    mip_level: level as u32,
+                    origin: Origin3d {
+                        x: slot.x >> level,
+                        y: slot.y >> level,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &image,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(image.width() * 4),
+                    rows_per_image: None,
+                },
+                Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
     }
 }
 
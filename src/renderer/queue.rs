@@ -0,0 +1,331 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use azalea_core::{
+    position::{ChunkPos, ChunkSectionPos},
+    resource_location::ResourceLocation,
+};
+use bevy::ecs::system::Resource;
+use parking_lot::Mutex;
+
+use super::mesher::ChunkAdded;
+
+/// The chunks waiting to be meshed, handed to the meshing task nearest to the camera first.
+/// Queueing a chunk that is already queued adds its sections to the queued ones, so sections
+/// queued twice are only meshed once.
+#[derive(Clone, Resource)]
+pub struct MeshQueue {
+    state: Arc<Mutex<QueueState>>,
+    wake_send: flume::Sender<()>,
+    wake_recv: flume::Receiver<()>,
+}
+
+/// Counts of the sections that went through a [`MeshQueue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MeshQueueStats {
+    /// How many sections are queued.
+    pub queued: usize,
+    /// How many sections were taken to be meshed.
+    pub taken: u64,
+    /// How many sections were queued while they were already queued.
+    pub merged: u64,
+}
+
+impl Default for MeshQueue {
+    fn default() -> Self {
+        // The task only needs to know that something was queued since it last looked.
+        let (wake_send, wake_recv) = flume::bounded(1);
+        Self {
+            state: Default::default(),
+            wake_send,
+            wake_recv,
+        }
+    }
+}
+
+impl MeshQueue {
+    pub fn push(&self, update: ChunkAdded) {
+        self.state.lock().push(update);
+        let _ = self.wake_send.try_send(());
+    }
+
+    /// Waits for a chunk to be queued and takes the one nearest to the camera.
+    pub async fn pop(&self) -> ChunkAdded {
+        loop {
+            if let Some(update) = self.state.lock().pop() {
+                return update;
+            }
+            // The queue holds a sender, so this never fails.
+            let _ = self.wake_recv.recv_async().await;
+        }
+    }
+
+    /// Moves the position chunks are prioritised by, the section the camera is in.
+    pub fn set_center(&self, center: ChunkSectionPos) {
+        self.state.lock().set_center(center);
+    }
+
+    /// Drops the queued sections of `chunks` in the world with `instance_name`.
+    pub fn remove_chunks(&self, instance_name: &ResourceLocation, chunks: &HashSet<ChunkPos>) {
+        self.state.lock().remove_chunks(instance_name, chunks);
+    }
+
+    pub fn stats(&self) -> MeshQueueStats {
+        self.state.lock().stats
+    }
+}
+
+struct QueuedChunk {
+    id: u64,
+    priority: u64,
+    update: ChunkAdded,
+}
+
+struct QueueState {
+    chunks: HashMap<(ResourceLocation, ChunkPos), QueuedChunk>,
+    ids: HashMap<u64, (ResourceLocation, ChunkPos)>,
+    /// The priority and id of every queued chunk. Entries whose priority doesn't match their
+    /// chunk's anymore are left in and skipped.
+    heap: BinaryHeap<Reverse<(u64, u64)>>,
+    next_id: u64,
+    center: ChunkSectionPos,
+    stats: MeshQueueStats,
+}
+
+impl Default for QueueState {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            ids: HashMap::new(),
+            heap: BinaryHeap::new(),
+            next_id: 0,
+            center: ChunkSectionPos::new(0, 0, 0),
+            stats: MeshQueueStats::default(),
+        }
+    }
+}
+
+impl QueueState {
+    fn push(&mut self, update: ChunkAdded) {
+        let key = (update.instance_name.clone(), update.pos);
+        let added = section_count(&update);
+
+        if let Some(queued) = self.chunks.get_mut(&key) {
+            let before = section_count(&queued.update);
+            let sections = match (queued.update.sections.take(), &update.sections) {
+                (Some(mut sections), Some(added)) => {
+                    sections.extend(added);
+                    Some(sections)
+                }
+                _ => None,
+            };
+            // The newest update has the world the chunk is in now.
+            queued.update = ChunkAdded { sections, ..update };
+
+            let after = section_count(&queued.update);
+            // A whole chunk can have fewer sections than were queued if some of them were outside
+            // of the world, so the old count is taken out first.
+            self.stats.queued = self.stats.queued - before + after;
+            self.stats.merged += (before + added - after) as u64;
+
+            let priority = priority(self.center, &queued.update);
+            if priority != queued.priority {
+                queued.priority = priority;
+                self.heap.push(Reverse((priority, queued.id)));
+            }
+            return;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let priority = priority(self.center, &update);
+
+        self.heap.push(Reverse((priority, id)));
+        self.ids.insert(id, key.clone());
+        self.chunks.insert(
+            key,
+            QueuedChunk {
+                id,
+                priority,
+                update,
+            },
+        );
+        self.stats.queued += added;
+    }
+
+    fn pop(&mut self) -> Option<ChunkAdded> {
+        while let Some(Reverse((priority, id))) = self.heap.pop() {
+            let Some(key) = self.ids.get(&id) else {
+                continue;
+            };
+            if self.chunks[key].priority != priority {
+                continue;
+            }
+
+            let key = self.ids.remove(&id).expect("id should be queued");
+            let queued = self.chunks.remove(&key).expect("chunk should be queued");
+
+            let count = section_count(&queued.update);
+            self.stats.queued -= count;
+            self.stats.taken += count as u64;
+            return Some(queued.update);
+        }
+        None
+    }
+
+    fn remove_chunks(&mut self, instance_name: &ResourceLocation, chunks: &HashSet<ChunkPos>) {
+        for pos in chunks {
+            // Its heap entries are skipped once the id is gone.
+            if let Some(queued) = self.chunks.remove(&(instance_name.clone(), *pos)) {
+                self.ids.remove(&queued.id);
+                self.stats.queued -= section_count(&queued.update);
+            }
+        }
+    }
+
+    fn set_center(&mut self, center: ChunkSectionPos) {
+        if center == self.center {
+            return;
+        }
+        self.center = center;
+
+        self.heap = self
+            .chunks
+            .values_mut()
+            .map(|queued| {
+                queued.priority = priority(center, &queued.update);
+                Reverse((queued.priority, queued.id))
+            })
+            .collect();
+    }
+}
+
+fn section_count(update: &ChunkAdded) -> usize {
+    match &update.sections {
+        Some(sections) => sections.len(),
+        None => update.section_range().len(),
+    }
+}
+
+/// The squared distance in sections from `center` to the nearest section of `update`.
+fn priority(center: ChunkSectionPos, update: &ChunkAdded) -> u64 {
+    let y = match &update.sections {
+        Some(sections) => sections
+            .range(center.y..)
+            .next()
+            .into_iter()
+            .chain(sections.range(..center.y).next_back())
+            .copied()
+            .min_by_key(|y| (y - center.y).abs())
+            .unwrap_or(center.y),
+        None => {
+            let range = update.section_range();
+            center.y.clamp(range.start, range.end - 1)
+        }
+    };
+
+    let dx = (update.pos.x - center.x) as i64;
+    let dy = (y - center.y) as i64;
+    let dz = (update.pos.z - center.z) as i64;
+    (dx * dx + dy * dy + dz * dz) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use azalea_core::{
+        position::{ChunkPos, ChunkSectionPos},
+        resource_location::ResourceLocation,
+    };
+
+    use super::{MeshQueueStats, QueueState};
+    use crate::renderer::mesher::ChunkAdded;
+
+    fn update(x: i32, z: i32, sections: Option<&[i32]>) -> ChunkAdded {
+        ChunkAdded {
+            pos: ChunkPos::new(x, z),
+            sections: sections.map(|sections| sections.iter().copied().collect()),
+            world: Default::default(),
+            instance_name: ResourceLocation::new("minecraft:overworld"),
+            constant_ambient_light: false,
+            has_sky_light: true,
+            min_y: -64,
+            height: 384,
+        }
+    }
+
+    fn pop(queue: &mut QueueState) -> Option<(i32, i32, Option<BTreeSet<i32>>)> {
+        queue
+            .pop()
+            .map(|update| (update.pos.x, update.pos.z, update.sections))
+    }
+
+    #[test]
+    fn queue_test() {
+        let mut queue = QueueState::default();
+        queue.push(update(5, 0, Some(&[0])));
+        queue.push(update(1, 1, Some(&[0])));
+        queue.push(update(2, 0, Some(&[4])));
+        // Queued again, the sections are merged.
+        queue.push(update(5, 0, Some(&[0, 1])));
+        assert_eq!(
+            queue.stats,
+            MeshQueueStats {
+                queued: 4,
+                taken: 0,
+                merged: 1,
+            }
+        );
+
+        assert_eq!(pop(&mut queue), Some((1, 1, Some([0].into()))));
+        // The camera moving changes which chunk is nearest.
+        queue.set_center(ChunkSectionPos::new(6, 0, 0));
+        assert_eq!(pop(&mut queue), Some((5, 0, Some([0, 1].into()))));
+        assert_eq!(pop(&mut queue), Some((2, 0, Some([4].into()))));
+        assert_eq!(pop(&mut queue), None);
+        assert_eq!(queue.stats.taken, 4);
+
+        // Queueing a whole chunk takes in its queued sections.
+        queue.push(update(0, 0, Some(&[2])));
+        queue.push(update(0, 0, None));
+        assert_eq!(queue.stats.queued, 24);
+        assert_eq!(pop(&mut queue), Some((0, 0, None)));
+
+        // Sections outside of the world don't count once the whole chunk is queued.
+        let outside: Vec<i32> = (-6..=20).collect();
+        queue.push(update(0, 0, Some(&outside)));
+        queue.push(update(0, 0, None));
+        assert_eq!(queue.stats.queued, 24);
+        assert_eq!(pop(&mut queue), Some((0, 0, None)));
+        assert_eq!(queue.stats.queued, 0);
+    }
+
+    #[test]
+    fn remove_test() {
+        let overworld = ResourceLocation::new("minecraft:overworld");
+        let nether = ResourceLocation::new("minecraft:the_nether");
+
+        let mut queue = QueueState::default();
+        queue.push(update(0, 0, Some(&[0, 1])));
+        queue.push(update(1, 0, Some(&[0])));
+        queue.push(update(2, 0, Some(&[0])));
+
+        // Forgotten chunks aren't meshed, the same chunk in another world is left alone.
+        queue.remove_chunks(&nether, &HashSet::from([ChunkPos::new(1, 0)]));
+        queue.remove_chunks(
+            &overworld,
+            &HashSet::from([ChunkPos::new(0, 0), ChunkPos::new(2, 0)]),
+        );
+        assert_eq!(queue.stats.queued, 1);
+        assert_eq!(pop(&mut queue), Some((1, 0, Some([0].into()))));
+        assert_eq!(pop(&mut queue), None);
+
+        // A chunk sent again after it was forgotten is queued like any other.
+        queue.push(update(0, 0, Some(&[3])));
+        assert_eq!(pop(&mut queue), Some((0, 0, Some([3].into()))));
+    }
+}